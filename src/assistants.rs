@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::client::{parse_response, ChatGPT, Organization};
+use crate::types::{FunctionDefinition, List, Model, Usage};
+
+/// The beta header the Assistants API requires
+const ASSISTANTS_BETA: (&str, &str) = ("OpenAI-Beta", "assistants=v2");
+
+/// An assistant, which answers the messages of threads with a model, instructions and tools
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Assistant {
+    /// Identifier of the assistant, e.g. `asst_abc123`
+    pub id: String,
+    /// Unix timestamp (in seconds) of when the assistant was created
+    #[serde(default)]
+    pub created_at: u64,
+    /// Name of the assistant
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Description of the assistant
+    #[serde(default)]
+    pub description: Option<String>,
+    /// ID of the model the assistant uses
+    pub model: String,
+    /// The system instructions of the assistant
+    #[serde(default)]
+    pub instructions: Option<String>,
+    /// The tools enabled on the assistant
+    #[serde(default)]
+    pub tools: Vec<AssistantTool>,
+    /// Key-value pairs attached to the assistant
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+/// A tool an assistant may use
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AssistantTool {
+    /// Lets the assistant write and run Python code
+    CodeInterpreter,
+    /// Lets the assistant search the files of its vector stores
+    FileSearch,
+    /// A function the assistant may call, whose calls are answered by the caller
+    Function {
+        /// The definition of the function
+        function: FunctionDefinition,
+    },
+    /// A tool unknown to this version of the crate
+    #[serde(other)]
+    Unknown,
+}
+
+/// Options of an assistant to create
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct AssistantOptions {
+    /// ID of the model to use. The client falls back to its default model when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<Model>,
+    /// Name of the assistant, at most 256 characters long
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Description of the assistant, at most 512 characters long
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The system instructions of the assistant
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    /// The tools enabled on the assistant, at most 128
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<AssistantTool>,
+    /// Up to 16 key-value pairs to attach to the assistant
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
+    /// Sampling temperature between 0 and 2
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+}
+
+/// A thread, which holds the messages of a conversation with assistants
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Thread {
+    /// Identifier of the thread, e.g. `thread_abc123`
+    pub id: String,
+    /// Unix timestamp (in seconds) of when the thread was created
+    #[serde(default)]
+    pub created_at: u64,
+    /// Key-value pairs attached to the thread
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+/// A message of a thread
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ThreadMessage {
+    /// Identifier of the message, e.g. `msg_abc123`
+    pub id: String,
+    /// ID of the thread the message belongs to
+    pub thread_id: String,
+    /// Unix timestamp (in seconds) of when the message was created
+    #[serde(default)]
+    pub created_at: u64,
+    /// Author of the message, either `user` or `assistant`
+    pub role: String,
+    /// The content of the message, in parts
+    #[serde(default)]
+    pub content: Vec<MessageContent>,
+    /// ID of the assistant that wrote the message, if any
+    #[serde(default)]
+    pub assistant_id: Option<String>,
+    /// ID of the run that wrote the message, if any
+    #[serde(default)]
+    pub run_id: Option<String>,
+}
+
+impl ThreadMessage {
+    /// Returns the text parts of the message, concatenated
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|content| match content {
+                MessageContent::Text { text } => Some(text.value.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// A part of the content of a [`ThreadMessage`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessageContent {
+    /// Text
+    Text {
+        /// The text and its annotations
+        text: MessageText,
+    },
+    /// The reason the assistant declined to answer
+    Refusal {
+        /// The refusal message
+        refusal: String,
+    },
+    /// A part unknown to this version of the crate, e.g. an image
+    #[serde(other)]
+    Unknown,
+}
+
+/// The text of a [`MessageContent::Text`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MessageText {
+    /// The text
+    pub value: String,
+    /// The file citations and paths in the text
+    #[serde(default)]
+    pub annotations: Vec<serde_json::Value>,
+}
+
+/// A run, which has an assistant answer the messages of a thread
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Run {
+    /// Identifier of the run, e.g. `run_abc123`
+    pub id: String,
+    /// ID of the thread the run answers
+    pub thread_id: String,
+    /// ID of the assistant running
+    pub assistant_id: String,
+    /// Status of the run
+    pub status: RunStatus,
+    /// Unix timestamp (in seconds) of when the run was created
+    #[serde(default)]
+    pub created_at: u64,
+    /// ID of the model the run uses
+    #[serde(default)]
+    pub model: String,
+    /// The action needed to continue the run, e.g. the outputs of function calls, when it [`RunStatus::RequiresAction`]
+    #[serde(default)]
+    pub required_action: Option<serde_json::Value>,
+    /// Why the run failed, when it [`RunStatus::Failed`]
+    #[serde(default)]
+    pub last_error: Option<RunError>,
+    /// The tokens used by the run, once it is finished
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+/// The status of a [`Run`]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    /// Waiting to start
+    Queued,
+    /// Running
+    InProgress,
+    /// Waiting for the outputs of function calls, see [`Run::required_action`]
+    RequiresAction,
+    /// Being cancelled
+    Cancelling,
+    /// Cancelled
+    Cancelled,
+    /// Failed, see [`Run::last_error`]
+    Failed,
+    /// Finished successfully
+    Completed,
+    /// Finished early, e.g. after reaching its token limit
+    Incomplete,
+    /// Expired before the function call outputs were submitted
+    Expired,
+    /// A status unknown to this version of the crate
+    #[serde(other)]
+    Unknown,
+}
+
+impl RunStatus {
+    /// Whether the run stopped progressing, either because it finished or because it waits for function call outputs
+    pub fn is_settled(&self) -> bool {
+        !matches!(
+            self,
+            RunStatus::Queued | RunStatus::InProgress | RunStatus::Cancelling
+        )
+    }
+}
+
+/// The error that made a [`Run`] fail
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RunError {
+    /// Machine-readable code of the error, e.g. `rate_limit_exceeded`
+    pub code: String,
+    /// Human-readable description of the error
+    pub message: String,
+}
+
+/// The methods of the Assistants API, which is in beta. They are not supported with Azure deployments
+impl ChatGPT {
+    /// Creates an assistant
+    pub async fn create_assistant(
+        &self,
+        mut options: AssistantOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<Assistant> {
+        options
+            .model
+            .get_or_insert_with(|| self.options.default_model.clone());
+        self.assistants_request(
+            Method::POST,
+            "assistants",
+            Some(serde_json::to_value(options)?),
+            org.into(),
+        )
+        .await
+    }
+
+    /// Creates an empty thread
+    pub async fn create_thread(&self, org: impl Into<Organization>) -> crate::Result<Thread> {
+        self.assistants_request(Method::POST, "threads", Some(json!({})), org.into())
+            .await
+    }
+
+    /// Adds a user message to a thread
+    pub async fn add_message<S: Into<String>>(
+        &self,
+        thread_id: &str,
+        content: S,
+        org: impl Into<Organization>,
+    ) -> crate::Result<ThreadMessage> {
+        let body = json!({ "role": "user", "content": content.into() });
+        self.assistants_request(
+            Method::POST,
+            &format!("threads/{thread_id}/messages"),
+            Some(body),
+            org.into(),
+        )
+        .await
+    }
+
+    /// Returns the messages of a thread, the newest first
+    pub async fn list_messages(
+        &self,
+        thread_id: &str,
+        org: impl Into<Organization>,
+    ) -> crate::Result<Vec<ThreadMessage>> {
+        let list: List<ThreadMessage> = self
+            .assistants_request(
+                Method::GET,
+                &format!("threads/{thread_id}/messages"),
+                None,
+                org.into(),
+            )
+            .await?;
+        Ok(list.data)
+    }
+
+    /// Starts a run of an assistant on a thread, which adds its answer to the thread once [`RunStatus::Completed`]
+    pub async fn create_run(
+        &self,
+        thread_id: &str,
+        assistant_id: &str,
+        org: impl Into<Organization>,
+    ) -> crate::Result<Run> {
+        let body = json!({ "assistant_id": assistant_id });
+        self.assistants_request(
+            Method::POST,
+            &format!("threads/{thread_id}/runs"),
+            Some(body),
+            org.into(),
+        )
+        .await
+    }
+
+    /// Retrieves the current state of a run
+    pub async fn retrieve_run(
+        &self,
+        thread_id: &str,
+        run_id: &str,
+        org: impl Into<Organization>,
+    ) -> crate::Result<Run> {
+        self.assistants_request(
+            Method::GET,
+            &format!("threads/{thread_id}/runs/{run_id}"),
+            None,
+            org.into(),
+        )
+        .await
+    }
+
+    /// Retrieves a run every `interval` until it is settled, see [`RunStatus::is_settled`], and returns it.
+    ///
+    /// Not available on `wasm32`, as waiting between the requests relies on the tokio timer.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn poll_run(
+        &self,
+        thread_id: &str,
+        run_id: &str,
+        interval: Duration,
+        org: impl Into<Organization>,
+    ) -> crate::Result<Run> {
+        let org = org.into();
+        loop {
+            let run = self.retrieve_run(thread_id, run_id, org.clone()).await?;
+            if run.status.is_settled() {
+                return Ok(run);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Sends a request to an endpoint of the Assistants API, with its beta header
+    async fn assistants_request<T: serde::de::DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+        org: Organization,
+    ) -> crate::Result<T> {
+        let mut request = self
+            .request(method, self.options.endpoint(path)?, org)
+            .header(ASSISTANTS_BETA.0, ASSISTANTS_BETA.1);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        parse_response(self.send(request).await?).await
+    }
+}
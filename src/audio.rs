@@ -0,0 +1,260 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+
+use bytes::Bytes;
+use reqwest::multipart::Form;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use crate::client::{parse_response, ChatGPT, Organization};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::files::file_name;
+use crate::files::file_part;
+use crate::types::Voice;
+
+/// The model used by transcriptions and translations when none is set
+pub const DEFAULT_TRANSCRIPTION_MODEL: &str = "whisper-1";
+
+/// The model used by text-to-speech when none is set
+pub const DEFAULT_SPEECH_MODEL: &str = "gpt-4o-mini-tts";
+
+/// Options of a text-to-speech request
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SpeechOptions {
+    /// ID of the model to use, e.g. `tts-1` or `tts-1-hd`, which defaults to [`DEFAULT_SPEECH_MODEL`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// The voice of the speech
+    pub voice: Voice,
+    /// The format of the audio, which defaults to [`SpeechFormat::Mp3`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<SpeechFormat>,
+    /// The speed of the speech, from 0.25 to 4. Defaults to 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+    /// Instructions on the tone or accent of the speech, e.g. `Speak cheerfully`. Not supported by `tts-1` and `tts-1-hd`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+}
+
+impl SpeechOptions {
+    /// Constructs options speaking with the voice, and the defaults of the API otherwise
+    pub fn new(voice: Voice) -> Self {
+        Self {
+            model: None,
+            voice,
+            response_format: None,
+            speed: None,
+            instructions: None,
+        }
+    }
+}
+
+/// The format of synthesized speech
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeechFormat {
+    /// MP3, for general use
+    Mp3,
+    /// Opus, for streaming over the internet with low latency
+    Opus,
+    /// AAC, for digital audio compression, e.g. on mobile devices
+    Aac,
+    /// FLAC, compressed without loss
+    Flac,
+    /// WAV, uncompressed, e.g. for low-latency applications
+    Wav,
+    /// Raw 16-bit little-endian PCM at 24kHz, without header
+    Pcm,
+}
+
+/// Options of a transcription or a translation of audio
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TranscriptionOptions {
+    /// ID of the model to use, which defaults to [`DEFAULT_TRANSCRIPTION_MODEL`]. Translations only support `whisper-1`
+    pub model: Option<String>,
+    /// The language of the audio, as an ISO-639-1 code, e.g. `fr`, which improves accuracy and latency.
+    /// It is detected when unset, and ignored by translations
+    pub language: Option<String>,
+    /// Text guiding the style of the transcript, or continuing a previous segment of the audio
+    pub prompt: Option<String>,
+    /// Sampling temperature, between 0 and 1. Defaults to 0, which lets the model raise it until the transcript is good enough
+    pub temperature: Option<f32>,
+    /// Requests the detected language and the duration of the audio along with the text, which only `whisper-1` supports
+    pub verbose: bool,
+}
+
+/// The text of a transcribed or translated audio
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Transcription {
+    /// The transcribed text, in English for translations
+    pub text: String,
+    /// The language of the audio, returned with [`TranscriptionOptions::verbose`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// The duration of the audio, in seconds, returned with [`TranscriptionOptions::verbose`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+}
+
+/// Builds the form of a transcription or translation request, sending the language hint only for transcriptions
+fn audio_form(
+    filename: &str,
+    audio: &Bytes,
+    options: &TranscriptionOptions,
+    with_language: bool,
+) -> Form {
+    let mut form = Form::new().part("file", file_part(filename, audio)).text(
+        "model",
+        options
+            .model
+            .clone()
+            .unwrap_or_else(|| DEFAULT_TRANSCRIPTION_MODEL.to_owned()),
+    );
+    if let Some(language) = options.language.clone().filter(|_| with_language) {
+        form = form.text("language", language);
+    }
+    if let Some(prompt) = options.prompt.clone() {
+        form = form.text("prompt", prompt);
+    }
+    if let Some(temperature) = options.temperature {
+        form = form.text("temperature", temperature.to_string());
+    }
+    if options.verbose {
+        form = form.text("response_format", "verbose_json");
+    }
+    form
+}
+
+/// The methods of the audio endpoints
+impl ChatGPT {
+    /// Transcribes an audio file, read from the disk without blocking, e.g. an mp3, wav or webm file, into text in its language.
+    ///
+    /// Not available on `wasm32`, which has no file system: use [`Self::transcribe_bytes`] there.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn transcribe<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: TranscriptionOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<Transcription> {
+        let path = path.as_ref();
+        let audio = tokio::fs::read(path).await?;
+        self.transcribe_bytes(file_name(path), audio, options, org)
+            .await
+    }
+
+    /// Like [`Self::transcribe`], but with the content of the audio file, whose format is inferred from `filename`, e.g. `speech.mp3`
+    pub async fn transcribe_bytes<N: Into<String>, B: Into<Bytes>>(
+        &self,
+        filename: N,
+        audio: B,
+        options: TranscriptionOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<Transcription> {
+        self.send_audio(
+            "audio/transcriptions",
+            filename.into(),
+            audio.into(),
+            options,
+            true,
+            org.into(),
+        )
+        .await
+    }
+
+    /// Translates an audio file, read from the disk without blocking, into English text, whatever its language.
+    ///
+    /// [`TranscriptionOptions::language`] is ignored, as the language of the audio is always detected.
+    /// Not available on `wasm32`, which has no file system: use [`Self::translate_bytes`] there.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn translate<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: TranscriptionOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<Transcription> {
+        let path = path.as_ref();
+        let audio = tokio::fs::read(path).await?;
+        self.translate_bytes(file_name(path), audio, options, org)
+            .await
+    }
+
+    /// Like [`Self::translate`], but with the content of the audio file, whose format is inferred from `filename`, e.g. `speech.mp3`
+    pub async fn translate_bytes<N: Into<String>, B: Into<Bytes>>(
+        &self,
+        filename: N,
+        audio: B,
+        options: TranscriptionOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<Transcription> {
+        self.send_audio(
+            "audio/translations",
+            filename.into(),
+            audio.into(),
+            options,
+            false,
+            org.into(),
+        )
+        .await
+    }
+
+    /// Sends a transcription or translation request, building its form again for every retry
+    async fn send_audio(
+        &self,
+        endpoint: &str,
+        filename: String,
+        audio: Bytes,
+        options: TranscriptionOptions,
+        with_language: bool,
+        org: Organization,
+    ) -> crate::Result<Transcription> {
+        let url = self.options.endpoint(endpoint)?;
+        let request = || {
+            let form = audio_form(&filename, &audio, &options, with_language);
+            self.request(Method::POST, url.clone(), org.clone())
+                .multipart(form)
+        };
+        let resp = self.send_rebuilding(request(), |_| Some(request())).await?;
+        parse_response(resp).await
+    }
+
+    /// Synthesizes speech reading the text aloud, and returns the audio, encoded in [`SpeechOptions::response_format`]
+    pub async fn speech<S: Into<String>>(
+        &self,
+        text: S,
+        mut options: SpeechOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<Bytes> {
+        options
+            .model
+            .get_or_insert_with(|| DEFAULT_SPEECH_MODEL.to_owned());
+        let mut body = serde_json::to_value(options)?;
+        body["input"] = text.into().into();
+        let url = self.options.endpoint("audio/speech")?;
+        let resp = self
+            .send(self.request(Method::POST, url, org.into()).json(&body))
+            .await?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(crate::err::Error::api_error(status, resp.text().await?));
+        }
+        Ok(resp.bytes().await?)
+    }
+
+    /// Like [`Self::speech`], but writes the audio to a file without blocking, which is created or overwritten.
+    ///
+    /// Not available on `wasm32`, which has no file system.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn speech_to_file<S: Into<String>, P: AsRef<Path>>(
+        &self,
+        text: S,
+        options: SpeechOptions,
+        path: P,
+        org: impl Into<Organization>,
+    ) -> crate::Result<()> {
+        let audio = self.speech(text, options, org).await?;
+        tokio::fs::write(path, audio).await?;
+        Ok(())
+    }
+}
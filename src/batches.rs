@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::client::{completion_body, parse_response, ChatGPT, Organization};
+use crate::types::{CompletionOptions, Message};
+
+/// The endpoint of chat completions, to pass to [`ChatGPT::create_batch`]
+pub const CHAT_COMPLETIONS_ENDPOINT: &str = "/v1/chat/completions";
+
+/// A batch of requests, processed asynchronously at a lower cost
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Batch {
+    /// Identifier of the batch, e.g. `batch_abc123`
+    pub id: String,
+    /// The endpoint the requests of the batch are sent to, e.g. `/v1/chat/completions`
+    pub endpoint: String,
+    /// ID of the uploaded JSONL file holding the requests
+    pub input_file_id: String,
+    /// The time frame within which the batch is processed, e.g. `24h`
+    pub completion_window: String,
+    /// The current status of the batch
+    pub status: BatchStatus,
+    /// ID of the JSONL file holding the responses of the successful requests, once available
+    #[serde(default)]
+    pub output_file_id: Option<String>,
+    /// ID of the JSONL file holding the errors of the failed requests, once available
+    #[serde(default)]
+    pub error_file_id: Option<String>,
+    /// Unix timestamp (in seconds) of when the batch was created
+    #[serde(default)]
+    pub created_at: u64,
+    /// Unix timestamp (in seconds) of when the batch finished, successfully or not
+    #[serde(default)]
+    pub completed_at: Option<u64>,
+    /// The progress of the requests of the batch
+    #[serde(default)]
+    pub request_counts: BatchRequestCounts,
+    /// The errors that made the validation of the input file fail
+    #[serde(default)]
+    pub errors: Option<BatchErrors>,
+    /// Key-value pairs attached to the batch
+    #[serde(default)]
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+/// The status of a [`Batch`]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    /// The input file is being validated
+    Validating,
+    /// The input file failed the validation, see [`Batch::errors`]
+    Failed,
+    /// The requests are being processed
+    InProgress,
+    /// The results are being prepared
+    Finalizing,
+    /// Finished, the results are in [`Batch::output_file_id`] and [`Batch::error_file_id`]
+    Completed,
+    /// Not finished within its completion window
+    Expired,
+    /// Being cancelled
+    Cancelling,
+    /// Cancelled
+    Cancelled,
+    /// A status unknown to this version of the crate
+    #[serde(other)]
+    Unknown,
+}
+
+impl BatchStatus {
+    /// Whether the batch stopped progressing, and its status won't change anymore
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            BatchStatus::Failed
+                | BatchStatus::Completed
+                | BatchStatus::Expired
+                | BatchStatus::Cancelled
+        )
+    }
+}
+
+/// The progress of the requests of a [`Batch`]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BatchRequestCounts {
+    /// Number of requests in the batch
+    pub total: u64,
+    /// Number of requests that completed successfully
+    pub completed: u64,
+    /// Number of requests that failed
+    pub failed: u64,
+}
+
+/// The errors of the validation of the input file of a [`Batch`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BatchErrors {
+    /// The errors, one per invalid line of the input file
+    #[serde(default)]
+    pub data: Vec<BatchError>,
+}
+
+/// An error of the validation of the input file of a [`Batch`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BatchError {
+    /// Machine-readable code of the error
+    #[serde(default)]
+    pub code: Option<String>,
+    /// Human-readable description of the error
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Line of the input file the error is about
+    #[serde(default)]
+    pub line: Option<u64>,
+}
+
+/// The methods of the Batch API
+impl ChatGPT {
+    /// Builds the JSONL input of a batch of chat completions, one `(custom_id, messages, options)` per request.
+    /// Requests without a model use the default model of the client.
+    ///
+    /// Write it to a file, upload it with [`FilePurpose::Batch`](crate::files::FilePurpose::Batch), then pass the ID of the file to [`ChatGPT::create_batch`].
+    pub fn batch_input(
+        &self,
+        requests: Vec<(String, Vec<Message>, CompletionOptions)>,
+    ) -> crate::Result<String> {
+        let mut input = String::new();
+        for (custom_id, messages, mut options) in requests {
+            // Batches don't support streaming
+            options.stream = None;
+            options.stream_options = None;
+            let line = json!({
+                "custom_id": custom_id,
+                "method": "POST",
+                "url": CHAT_COMPLETIONS_ENDPOINT,
+                "body": completion_body(messages, options, &self.options)?,
+            });
+            input.push_str(&serde_json::to_string(&line)?);
+            input.push('\n');
+        }
+        Ok(input)
+    }
+
+    /// Creates a batch from an uploaded JSONL file of requests to an endpoint, e.g. [`CHAT_COMPLETIONS_ENDPOINT`], processed within a completion window, e.g. `24h`
+    pub async fn create_batch(
+        &self,
+        input_file_id: &str,
+        endpoint: &str,
+        completion_window: &str,
+        org: impl Into<Organization>,
+    ) -> crate::Result<Batch> {
+        let body = json!({
+            "input_file_id": input_file_id,
+            "endpoint": endpoint,
+            "completion_window": completion_window,
+        });
+        let request = self
+            .request(Method::POST, self.options.endpoint("batches")?, org.into())
+            .json(&body);
+        parse_response(self.send(request).await?).await
+    }
+
+    /// Retrieves the current state of a batch
+    pub async fn retrieve_batch(
+        &self,
+        batch_id: &str,
+        org: impl Into<Organization>,
+    ) -> crate::Result<Batch> {
+        let url = self.options.endpoint(&format!("batches/{batch_id}"))?;
+        parse_response(
+            self.send(self.request(Method::GET, url, org.into()))
+                .await?,
+        )
+        .await
+    }
+
+    /// Cancels a batch, which stays [`BatchStatus::Cancelling`] for a while before being [`BatchStatus::Cancelled`]
+    pub async fn cancel_batch(
+        &self,
+        batch_id: &str,
+        org: impl Into<Organization>,
+    ) -> crate::Result<Batch> {
+        let url = self
+            .options
+            .endpoint(&format!("batches/{batch_id}/cancel"))?;
+        parse_response(
+            self.send(self.request(Method::POST, url, org.into()))
+                .await?,
+        )
+        .await
+    }
+}
@@ -1,13 +1,88 @@
 #![warn(missing_docs)]
 #![doc = include_str!("../README.md")]
 
+#[cfg(all(feature = "rustls-tls", feature = "native-tls"))]
+compile_error!(
+    "the `rustls-tls` and `native-tls` features are mutually exclusive, disable the default features to use `native-tls`"
+);
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    not(any(feature = "rustls-tls", feature = "native-tls"))
+))]
+compile_error!("either the `rustls-tls` or the `native-tls` feature must be enabled");
+
+/// This module contains the Assistants API, with assistants, threads and runs
+pub mod assistants;
+/// This module contains the audio endpoints, to transcribe, translate and synthesize speech
+pub mod audio;
+/// This module contains the Batch API, to process large volumes of requests asynchronously
+pub mod batches;
 /// This module contains the ChatGPT client
 pub mod client;
+/// This module contains the conversation, which keeps track of the message history
+pub mod conversation;
 /// This module contains the errors related to the API
 pub mod err;
+/// This module contains the endpoints to upload and manage files
+pub mod files;
+/// This module contains the registry of functions the model may call
+pub mod functions;
+/// This module contains the interceptors called around every request
+pub mod interceptor;
 /// The prelude module. Import everything from it to get the necessary elements from this library
+///
+/// ```no_run
+/// use chatgpt::prelude::*;
+///
+/// # async fn example() -> chatgpt::Result<()> {
+/// let client = ChatGPT::with_options("sk-...", ClientOptions::default())?;
+/// let options = CompletionOptions::preset(Preset::Precise).with_model(Model::Gpt4o);
+/// let messages = vec![
+///     Message::from((Role::System, "Answer in one word")),
+///     Message::from("What is the capital of France?"),
+/// ];
+/// let response: ConversationResponse = client
+///     .send_message_full(messages, options.clone(), String::new())
+///     .await?;
+/// if response.choices[0].finish_reason == Some(FinishReason::Length) {
+///     println!("The answer was truncated");
+/// }
+///
+/// let mut stream = client
+///     .send_message_streaming("Hello", options, String::new())
+///     .await?;
+/// while let Some(part) = stream.next().await {
+///     if let ResponsePart::Chunk(chunk) = part? {
+///         print!("{}", chunk.choices[0].delta.content.as_deref().unwrap_or_default());
+///     }
+/// }
+///
+/// let mut conversation: Conversation = client.new_conversation(String::new());
+/// let mut functions = FunctionRegistry::new();
+/// functions.register("now", "Returns the current time", serde_json::json!({}), |_| {
+///     Ok(serde_json::json!("12:00"))
+/// });
+/// conversation.send_with_tools("What time is it?", &functions).await?;
+/// # Ok(())
+/// # }
+/// ```
 pub mod prelude;
+/// This module contains the pricing table used to estimate the cost of requests
+pub mod pricing;
+/// This module contains the rate limits returned by the API and the throttling based on them
+pub mod ratelimit;
+/// This module contains helpers to process streamed responses
+pub mod stream;
+/// This module contains the local tokenizer used to estimate the number of tokens of messages
+#[cfg(feature = "tokenizer")]
+pub mod tokenizer;
+/// This module contains the transport sending the requests, which can replace the built-in HTTP client
+#[cfg(not(target_arch = "wasm32"))]
+pub mod transport;
 /// Types returned from the API and sent to it
+///
+/// Response types are forward compatible: unknown fields are ignored, fields that not every backend returns default
+/// to empty values, and enums returned by the API have an `Unknown` variant for values added after this version.
 pub mod types;
 
 /// Result that is returned from most ChatGPT functions
@@ -15,44 +90,2449 @@ pub type Result<T> = std::result::Result<T, err::Error>;
 
 #[cfg(test)]
 pub mod test {
-    use crate::{client::ChatGPT, types::Message, types::ResponsePart};
+    use crate::{
+        assistants::{AssistantOptions, AssistantTool, RunStatus},
+        audio::{SpeechFormat, SpeechOptions, TranscriptionOptions},
+        batches::{BatchStatus, CHAT_COMPLETIONS_ENDPOINT},
+        client::{ChatGPT, ClientOptions, Organization, ProxyConfig},
+        conversation::Conversation,
+        err::Error,
+        files::FilePurpose,
+        interceptor::{Interceptor, InterceptorFuture},
+        transport::{HttpClient, ResponseBody, ResponseFuture},
+        types::{
+            CompletionOptions, EmbeddingOptions, EncodingFormat, Message, Model, ResponsePart,
+            Role, Usage, Voice,
+        },
+    };
+    use flate2::{write::GzEncoder, Compression};
     use futures_util::StreamExt;
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::{StatusCode, Url};
+    use serde_json::json;
+    use std::io::Write;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use wiremock::{
+        matchers::{body_json, body_partial_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    /// Constructs a client sending its requests to the mock server
+    fn mock_client(server: &MockServer) -> ChatGPT {
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        ChatGPT::with_options(
+            "sk-test",
+            ClientOptions::default().with_backend_api_url(url),
+        )
+        .unwrap()
+    }
+
+    /// Serves the response to every request over HTTP/1.1 keep-alive, counting the accepted connections
+    async fn counting_server(response: serde_json::Value) -> (Url, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/v1/", listener.local_addr().unwrap())).unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        let response = response.to_string();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                let response = response.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buffer = [0; 4096];
+                    loop {
+                        let read = socket.read(&mut buffer).await.unwrap_or(0);
+                        if read == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&buffer[..read]);
+                        let head = String::from_utf8_lossy(&request).to_lowercase();
+                        let Some(head_len) = head.find("\r\n\r\n") else {
+                            continue;
+                        };
+                        let body_len: usize = head[..head_len]
+                            .lines()
+                            .find_map(|line| line.strip_prefix("content-length:"))
+                            .map_or(0, |len| len.trim().parse().unwrap());
+                        if request.len() < head_len + 4 + body_len {
+                            continue;
+                        }
+                        request.drain(..head_len + 4 + body_len);
+                        let reply = format!(
+                            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                            response.len(),
+                            response
+                        );
+                        socket.write_all(reply.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+        (url, connections)
+    }
+
+    fn user_message(content: &str) -> Vec<Message> {
+        vec![Message {
+            role: "user".to_owned(),
+            content: Some(content.into()),
+            ..Default::default()
+        }]
+    }
+
     #[tokio::test]
+    #[ignore = "requires a live OPENAI_SK"]
     async fn test_client() {
         let token = std::env::var("OPENAI_SK").unwrap();
         let client = ChatGPT::new(&token).unwrap();
     }
     #[tokio::test]
+    #[ignore = "requires a live OPENAI_SK"]
     async fn test_message_streaming() -> crate::Result<()> {
-    let org = "org-xzE2hBner5ZwF3wAyvMytmsd".to_string();
+        let org = "org-xzE2hBner5ZwF3wAyvMytmsd".to_string();
         let token = std::env::var("OPENAI_SK").unwrap();
         // std::env::var("SESSION_TOKEN").unwrap();
         let messages = vec![Message {
             role: "user".to_owned(),
-            content: "Write me a simple sorting algorithm in Rust".to_owned(),
+            content: Some("Write me a simple sorting algorithm in Rust".into()),
+            ..Default::default()
         }];
         let client = ChatGPT::new(&token)?;
-        let mut stream = client.send_message_streaming(messages, Default::default(), org).await?;
-		while let Some(chunk) = stream.next().await {
-			dbg!(&chunk);
-		}
-		panic!("panic");
-		Ok(())
-	}
+        let mut stream = client
+            .send_message_streaming(messages, Default::default(), org)
+            .await?;
+        while let Some(chunk) = stream.next().await {
+            dbg!(&chunk);
+        }
+        Ok(())
+    }
     #[tokio::test]
+    #[ignore = "requires a live OPENAI_SK"]
     async fn test_message() -> crate::Result<()> {
-    let org = "org-xzE2hBner5ZwF3wAyvMytmsd".to_string();
+        let org = "org-xzE2hBner5ZwF3wAyvMytmsd".to_string();
         let token = std::env::var("OPENAI_SK").unwrap();
         // std::env::var("SESSION_TOKEN").unwrap();
         let messages = vec![Message {
             role: "user".to_owned(),
-            content: "Write me a simple sorting algorithm in Rust".to_owned(),
+            content: Some("Write me a simple sorting algorithm in Rust".into()),
+            ..Default::default()
         }];
         let client = ChatGPT::new(&token)?;
-        let response = client.send_message_full(messages, Default::default(), org).await?;
+        let response = client
+            .send_message_full(messages, Default::default(), org)
+            .await?;
         println!("{:?}", response);
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_mock_message() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(header("Authorization", "Bearer sk-test"))
+            .and(header("OpenAI-Organization", "org-test"))
+            .and(body_partial_json(json!({
+                "model": "gpt-4o-mini",
+                "messages": [{ "role": "user", "content": "Hello" }],
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hello there!" },
+                    "finish_reason": "stop",
+                }],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 3, "total_tokens": 12 },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let response = mock_client(&server)
+            .send_message(
+                user_message("Hello"),
+                Default::default(),
+                "org-test".to_owned(),
+            )
+            .await?;
+        assert_eq!(response, "Hello there!");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_message_all() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(body_partial_json(json!({ "n": 3 })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [
+                    { "index": 0, "message": { "role": "assistant", "content": "Hi!" } },
+                    { "index": 1, "message": { "role": "assistant", "content": null } },
+                    { "index": 2, "message": { "role": "assistant", "content": "Hello!" } },
+                ],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 4, "total_tokens": 13 },
+            })))
+            .mount(&server)
+            .await;
+
+        let options = CompletionOptions {
+            n: Some(3),
+            ..Default::default()
+        };
+        let responses = mock_client(&server)
+            .send_message_all("Hello", options, String::new())
+            .await?;
+        assert_eq!(responses, vec!["Hi!", "Hello!"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_message_streaming() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        let chunk = |content: &str| {
+            json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion.chunk",
+                "created": 1677652288,
+                "model": "gpt-4",
+                "choices": [{ "index": 0, "delta": { "content": content } }],
+            })
+        };
+        let body = format!(
+            "data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            chunk("Hello"),
+            chunk(" there!")
+        );
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(body_partial_json(
+                json!({ "model": "gpt-4o-mini", "stream": true }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let parts: Vec<ResponsePart> = mock_client(&server)
+            .send_message_streaming(user_message("Hello"), Default::default(), String::new())
+            .await?
+            .map(|part| part.unwrap())
+            .collect()
+            .await;
+        let content: String = parts
+            .iter()
+            .filter_map(|part| match part {
+                ResponsePart::Chunk(chunk) => chunk.choices[0].delta.content.clone(),
+                ResponsePart::Done | ResponsePart::Error(_) => None,
+            })
+            .collect();
+        assert_eq!(content, "Hello there!");
+        assert_eq!(parts.last(), Some(&ResponsePart::Done));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_stream_error_event() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        let chunk = json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion.chunk",
+            "created": 1677652288,
+            "choices": [{ "index": 0, "delta": { "content": "Hello" } }],
+        });
+        let error = json!({
+            "error": {
+                "message": "The server had an error while processing your request.",
+                "type": "server_error",
+                "param": null,
+                "code": null,
+            },
+        });
+        let body = format!("data: {chunk}\n\ndata: {error}\n\ndata: [DONE]\n\n");
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/broken/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Encoding", "gzip")
+                    .set_body_raw("not gzip", "text/event-stream"),
+            )
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let parts: Vec<crate::Result<ResponsePart>> = client
+            .send_message_streaming("Hello", Default::default(), String::new())
+            .await?
+            .collect()
+            .await;
+        assert_eq!(parts.len(), 3);
+        assert!(matches!(parts[0], Ok(ResponsePart::Chunk(_))));
+        match &parts[1] {
+            Ok(ResponsePart::Error(error)) => {
+                assert_eq!(error.kind.as_deref(), Some("server_error"))
+            }
+            other => panic!("expected an error event, got {other:?}"),
+        }
+        assert!(matches!(parts[2], Ok(ResponsePart::Done)));
+
+        let stream = client
+            .send_message_streaming("Hello", Default::default(), String::new())
+            .await?;
+        assert!(matches!(
+            crate::stream::collect_response(stream).await,
+            Err(Error::ApiError { error: Some(_), .. })
+        ));
+
+        let url = Url::parse(&format!("{}/v1/broken/", server.uri())).unwrap();
+        let broken = ChatGPT::with_options(
+            "sk-test",
+            ClientOptions::default().with_backend_api_url(url),
+        )?;
+        let parts: Vec<crate::Result<ResponsePart>> = broken
+            .send_message_streaming("Hello", Default::default(), String::new())
+            .await?
+            .collect()
+            .await;
+        assert!(matches!(parts.last(), Some(Err(Error::StreamError(_)))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_message_collected() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        let chunk = |content: &str| {
+            json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion.chunk",
+                "created": 1677652288,
+                "model": "gpt-4o-mini",
+                "choices": [{ "index": 0, "delta": { "content": content } }],
+            })
+        };
+        let usage = json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion.chunk",
+            "created": 1677652288,
+            "model": "gpt-4o-mini",
+            "choices": [],
+            "usage": { "prompt_tokens": 9, "completion_tokens": 3, "total_tokens": 12 },
+        });
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({
+                "stream": true,
+                "stream_options": { "include_usage": true },
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                format!(
+                    "data: {}\n\ndata: {}\n\ndata: {usage}\n\ndata: [DONE]\n\n",
+                    chunk("Hello"),
+                    chunk(" there!")
+                ),
+                "text/event-stream",
+            ))
+            .mount(&server)
+            .await;
+
+        let response = mock_client(&server)
+            .send_message_collected("Hello", Default::default(), String::new())
+            .await?;
+        assert_eq!(
+            response.choices[0].message.content,
+            Some("Hello there!".into())
+        );
+        assert_eq!(response.usage.total_tokens, 12);
+        assert!(!response.usage.estimated);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_stream_to_channel() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        let chunk = json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion.chunk",
+            "created": 1677652288,
+            "model": "gpt-4",
+            "choices": [{ "index": 0, "delta": { "content": "Hello" } }],
+        });
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                format!("data: {chunk}\n\ndata: [DONE]\n\n"),
+                "text/event-stream",
+            ))
+            .mount(&server)
+            .await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        mock_client(&server)
+            .stream_to_channel("Hello", Default::default(), String::new(), tx)
+            .await?;
+        assert!(matches!(rx.recv().await, Some(ResponsePart::Chunk(_))));
+        assert_eq!(rx.recv().await, Some(ResponsePart::Done));
+        assert_eq!(rx.recv().await, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_compressed_stream() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        let chunk = |content: &str| {
+            json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion.chunk",
+                "created": 1677652288,
+                "model": "gpt-4",
+                "choices": [{ "index": 0, "delta": { "content": content } }],
+            })
+        };
+        let body = format!(
+            "data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            chunk("Hello"),
+            chunk(" there!")
+        );
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body.as_bytes()).unwrap();
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Encoding", "gzip")
+                    .set_body_raw(encoder.finish().unwrap(), "text/event-stream"),
+            )
+            .mount(&server)
+            .await;
+
+        let stream = mock_client(&server)
+            .send_message_streaming("Hello", Default::default(), String::new())
+            .await?;
+        let parts: Vec<ResponsePart> = stream.map(|part| part.unwrap()).collect().await;
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[2], ResponsePart::Done);
+
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        let options = ClientOptions::default()
+            .with_backend_api_url(url)
+            .with_compression(false);
+        let stream = ChatGPT::with_options("sk-test", options)?
+            .send_message_streaming("Hello", Default::default(), String::new())
+            .await?;
+        drop(stream);
+        let requests = server.received_requests().await.unwrap();
+        let accept_encoding = requests[0].headers["Accept-Encoding"].to_str().unwrap();
+        assert!(accept_encoding.contains("gzip") && accept_encoding.contains("br"));
+        assert!(requests[1].headers.get("Accept-Encoding").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_connection_reuse() -> crate::Result<()> {
+        let (url, connections) = counting_server(json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1677652288,
+            "choices": [{ "index": 0, "message": { "role": "assistant", "content": "Hi!" } }],
+            "usage": { "prompt_tokens": 9, "completion_tokens": 2, "total_tokens": 11 },
+        }))
+        .await;
+        let client = ChatGPT::with_options(
+            "sk-test",
+            ClientOptions::default().with_backend_api_url(url),
+        )?;
+        for _ in 0..5 {
+            client
+                .send_message("Hello", Default::default(), String::new())
+                .await?;
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_complete() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/completions"))
+            .and(header("Authorization", "Bearer sk-test"))
+            .and(body_partial_json(json!({
+                "model": "gpt-3.5-turbo-instruct",
+                "prompt": "Say this is a test",
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "cmpl-123",
+                "object": "text_completion",
+                "created": 1677652288,
+                "model": "gpt-3.5-turbo-instruct",
+                "choices": [{ "text": "This is a test.", "index": 0, "finish_reason": "stop" }],
+                "usage": { "prompt_tokens": 5, "completion_tokens": 5, "total_tokens": 10 },
+            })))
+            .mount(&server)
+            .await;
+
+        let text = mock_client(&server)
+            .complete("Say this is a test", Default::default(), String::new())
+            .await?;
+        assert_eq!(text, "This is a test.");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_message_parsed() -> crate::Result<()> {
+        #[derive(Debug, serde::Deserialize, PartialEq)]
+        struct Person {
+            name: String,
+            age: u32,
+        }
+
+        let server = MockServer::start().await;
+        let completion = |content: &str| {
+            json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [{ "index": 0, "message": { "role": "assistant", "content": content } }],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 12, "total_tokens": 21 },
+            })
+        };
+        Mock::given(method("POST"))
+            .and(body_partial_json(
+                json!({ "response_format": { "type": "json_object" } }),
+            ))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(completion(r#"{"name":"Ada","age":36}"#)),
+            )
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(completion("Ada, 36")))
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let person: Person = client
+            .send_message_parsed("Describe Ada as JSON", Default::default(), String::new())
+            .await?;
+        assert_eq!(
+            person,
+            Person {
+                name: "Ada".to_owned(),
+                age: 36
+            }
+        );
+
+        let result = client
+            .send_message_parsed::<Person, _>("Describe Ada", Default::default(), String::new())
+            .await;
+        match result {
+            Err(Error::ContentParseError { content, .. }) => assert_eq!(content, "Ada, 36"),
+            other => panic!("expected a content parse error, got {other:?}"),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_stream_first_byte_timeout() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw("data: [DONE]\n\n", "text/event-stream")
+                    .set_delay(std::time::Duration::from_secs(5)),
+            )
+            .mount(&server)
+            .await;
+
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        let options = ClientOptions::default()
+            .with_backend_api_url(url)
+            .with_stream_idle_timeout(std::time::Duration::from_millis(100));
+        let result = ChatGPT::with_options("sk-test", options)
+            .unwrap()
+            .send_message_streaming("Hello", Default::default(), String::new())
+            .await;
+        assert!(matches!(result, Err(Error::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn test_mock_first_token_timeout() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw("data: [DONE]\n\n", "text/event-stream")
+                    .set_delay(std::time::Duration::from_secs(5)),
+            )
+            .mount(&server)
+            .await;
+
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        let options = ClientOptions::default()
+            .with_backend_api_url(url)
+            .with_stream_idle_timeout(std::time::Duration::from_secs(10))
+            .with_first_token_timeout(std::time::Duration::from_millis(100));
+        let result = ChatGPT::with_options("sk-test", options)
+            .unwrap()
+            .send_message_streaming("Hello", Default::default(), String::new())
+            .await;
+        assert!(matches!(result, Err(Error::Timeout(message)) if message.contains("first token")));
+    }
+
+    #[tokio::test]
+    async fn test_mock_stream_connect_retry() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        let chunk = json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion.chunk",
+            "created": 1677652288,
+            "choices": [{ "index": 0, "delta": { "content": "Hello there!" } }],
+        });
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                format!("data: {chunk}\n\ndata: [DONE]\n\n"),
+                "text/event-stream",
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        let options = ClientOptions::default()
+            .with_backend_api_url(url)
+            .with_stream_connect_deadline(std::time::Duration::from_secs(5));
+        let stream = ChatGPT::with_options("sk-test", options)?
+            .send_message_streaming("Hello", Default::default(), String::new())
+            .await?;
+        let response = crate::stream::collect_response(stream).await?;
+        assert_eq!(
+            response.choices[0].message.content,
+            Some("Hello there!".into())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_stream_connect_without_retry() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let result = mock_client(&server)
+            .send_message_streaming("Hello", Default::default(), String::new())
+            .await;
+        assert!(matches!(result, Err(Error::ApiError { status, .. }) if status == 503));
+    }
+
+    #[tokio::test]
+    async fn test_mock_stream_resumption() -> crate::Result<()> {
+        let chunk = |content: &str| {
+            json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion.chunk",
+                "created": 1677652288,
+                "choices": [{ "index": 0, "delta": { "content": content } }],
+            })
+        };
+        let (hello, there) = (chunk("Hello"), chunk(" there!"));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/v1/", listener.local_addr().unwrap())).unwrap();
+        let last_event_ids = Arc::new(Mutex::new(Vec::new()));
+        let recorded = last_event_ids.clone();
+        tokio::spawn(async move {
+            for connection in 0.. {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                while !String::from_utf8_lossy(&request).ends_with("}") {
+                    let read = socket.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let request = String::from_utf8_lossy(&request).to_lowercase();
+                recorded.lock().unwrap().push(
+                    request
+                        .lines()
+                        .find_map(|line| line.strip_prefix("last-event-id: "))
+                        .map(str::to_owned),
+                );
+                // Every other response is cut after its first event, as if the connection dropped, and the others replay it
+                let (body, length) = match connection % 2 {
+                    0 => {
+                        let body = format!("id: 1\ndata: {hello}\n\n");
+                        let length = body.len() + 100;
+                        (body, length)
+                    }
+                    _ => {
+                        let body = format!(
+                            "id: 1\ndata: {hello}\n\nid: 2\ndata: {there}\n\ndata: [DONE]\n\n"
+                        );
+                        let length = body.len();
+                        (body, length)
+                    }
+                };
+                let reply = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncontent-length: {length}\r\n\r\n{body}"
+                );
+                socket.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        let options = ClientOptions::default()
+            .with_backend_api_url(url.clone())
+            .with_stream_resumption(1);
+        let stream = ChatGPT::with_options("sk-test", options)?
+            .send_message_streaming("Hello", Default::default(), String::new())
+            .await?;
+        let response = crate::stream::collect_response(stream).await?;
+        assert_eq!(
+            response.choices[0].message.content,
+            Some("Hello there!".into())
+        );
+
+        let options = ClientOptions::default().with_backend_api_url(url);
+        let stream = ChatGPT::with_options("sk-test", options)?
+            .send_message_streaming("Hello", Default::default(), String::new())
+            .await?;
+        assert!(matches!(
+            crate::stream::collect_response(stream).await,
+            Err(Error::StreamError(_))
+        ));
+        assert_eq!(
+            *last_event_ids.lock().unwrap(),
+            [None, Some("1".to_owned()), None]
+        );
+        Ok(())
+    }
+
+    /// Answers every request with the next of its bodies, recording the requests without any network
+    struct FakeTransport {
+        bodies: Mutex<Vec<&'static str>>,
+        requests: Mutex<Vec<http::Request<bytes::Bytes>>>,
+    }
+
+    impl HttpClient for FakeTransport {
+        fn execute(&self, request: http::Request<bytes::Bytes>) -> ResponseFuture<'_> {
+            self.requests.lock().unwrap().push(request);
+            let body = self.bodies.lock().unwrap().remove(0);
+            // Sent in two chunks, like a response received over the network
+            let (start, end) = body.split_at(body.len() / 2);
+            let chunks = [start, end].map(|chunk| Ok(bytes::Bytes::from_static(chunk.as_bytes())));
+            let body: ResponseBody = Box::pin(futures_util::stream::iter(chunks));
+            Box::pin(async move { Ok(http::Response::new(body)) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fake_transport() -> crate::Result<()> {
+        let completion = r#"{"id":"chatcmpl-123","object":"chat.completion","created":1677652288,"choices":[{"index":0,"message":{"role":"assistant","content":"Hello there!"}}]}"#;
+        let stream = "data: {\"id\":\"chatcmpl-123\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi!\"}}]}\n\ndata: [DONE]\n\n";
+        let file = r#"{"id":"file-abc123","bytes":3,"created_at":1699000000,"purpose":"batch"}"#;
+        let transport = Arc::new(FakeTransport {
+            bodies: Mutex::new(vec![completion, stream, file]),
+            requests: Mutex::default(),
+        });
+        let options = ClientOptions::default().with_http_client(transport.clone());
+        let client = ChatGPT::with_options("sk-test", options)?;
+
+        let response = client
+            .send_message("Hello", Default::default(), String::new())
+            .await?;
+        assert_eq!(response, "Hello there!");
+        let stream = client
+            .send_message_streaming("Hi", Default::default(), String::new())
+            .await?;
+        let response = crate::stream::collect_response(stream).await?;
+        assert_eq!(response.choices[0].message.content, Some("Hi!".into()));
+        let uploaded = client
+            .upload_file_bytes("input.jsonl", "{}\n", FilePurpose::Batch, String::new())
+            .await?;
+        assert_eq!(uploaded.id, "file-abc123");
+
+        let requests = transport.requests.lock().unwrap();
+        let json = |index: usize| -> serde_json::Value {
+            serde_json::from_slice(requests[index].body()).unwrap()
+        };
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].method(), http::Method::POST);
+        assert_eq!(
+            requests[0].uri(),
+            "https://api.openai.com/v1/chat/completions"
+        );
+        assert_eq!(requests[0].headers()["authorization"], "Bearer sk-test");
+        assert_eq!(json(0)["messages"][0]["content"], "Hello");
+        assert_eq!(json(1)["stream"], true);
+        // The multipart form is read whole before being handed to the transport
+        let form = String::from_utf8_lossy(requests[2].body());
+        assert!(form.contains("filename=\"input.jsonl\""));
+        assert!(form.contains("name=\"purpose\"\r\n\r\nbatch\r\n"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_reqwest_transport() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(header("authorization", "Bearer sk-test"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hello there!" },
+                }],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        let options = ClientOptions::default()
+            .with_backend_api_url(url)
+            .with_http_client(Arc::new(reqwest::Client::new()));
+        let response = ChatGPT::with_options("sk-test", options)?
+            .send_message("Hello", Default::default(), String::new())
+            .await?;
+        assert_eq!(response, "Hello there!");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_proxy() -> crate::Result<()> {
+        let proxy = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(header("Host", "api.example.invalid"))
+            .and(header("Proxy-Authorization", "Basic dXNlcjpzZWNyZXQ="))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hello there!" },
+                }],
+            })))
+            .expect(1)
+            .mount(&proxy)
+            .await;
+
+        let proxy_config =
+            ProxyConfig::new(Url::parse(&proxy.uri()).unwrap()).with_basic_auth("user", "secret");
+        assert!(!format!("{proxy_config:?}").contains("secret"));
+        let options = ClientOptions::default()
+            .with_backend_api_url(Url::parse("http://api.example.invalid/v1/").unwrap())
+            .with_proxy(proxy_config);
+        let response = ChatGPT::with_options("sk-test", options)?
+            .send_message("Hello", Default::default(), String::new())
+            .await?;
+        assert_eq!(response, "Hello there!");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_assistants() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        let mock = |http_method: &str, endpoint: &str| {
+            Mock::given(method(http_method))
+                .and(path(format!("/v1/{endpoint}")))
+                .and(header("OpenAI-Beta", "assistants=v2"))
+                .and(header("Authorization", "Bearer sk-test"))
+        };
+        let ok = |body: serde_json::Value| ResponseTemplate::new(200).set_body_json(body);
+        let run = |status: &str| {
+            json!({
+                "id": "run_1",
+                "object": "thread.run",
+                "thread_id": "thread_1",
+                "assistant_id": "asst_1",
+                "status": status,
+                "created_at": 1699000000,
+                "model": "gpt-4o-mini",
+            })
+        };
+        let message = |id: &str, role: &str, text: &str| {
+            json!({
+                "id": id,
+                "object": "thread.message",
+                "thread_id": "thread_1",
+                "created_at": 1699000000,
+                "role": role,
+                "content": [{ "type": "text", "text": { "value": text, "annotations": [] } }],
+            })
+        };
+        mock("POST", "assistants")
+            .and(body_partial_json(
+                json!({ "model": "gpt-4o-mini", "name": "Math tutor" }),
+            ))
+            .respond_with(ok(json!({
+                "id": "asst_1",
+                "object": "assistant",
+                "created_at": 1699000000,
+                "name": "Math tutor",
+                "model": "gpt-4o-mini",
+                "instructions": "You are a math tutor.",
+                "tools": [{ "type": "code_interpreter" }, { "type": "computer_use" }],
+                "metadata": {},
+            })))
+            .mount(&server)
+            .await;
+        mock("POST", "threads")
+            .respond_with(ok(json!({
+                "id": "thread_1",
+                "object": "thread",
+                "created_at": 1699000000,
+                "metadata": {},
+            })))
+            .mount(&server)
+            .await;
+        mock("POST", "threads/thread_1/messages")
+            .and(body_partial_json(json!({ "role": "user" })))
+            .respond_with(ok(message("msg_1", "user", "What is 3x + 11 = 14?")))
+            .mount(&server)
+            .await;
+        mock("POST", "threads/thread_1/runs")
+            .and(body_partial_json(json!({ "assistant_id": "asst_1" })))
+            .respond_with(ok(run("queued")))
+            .mount(&server)
+            .await;
+        mock("GET", "threads/thread_1/runs/run_1")
+            .respond_with(ok(run("in_progress")))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        mock("GET", "threads/thread_1/runs/run_1")
+            .respond_with(ok(run("completed")))
+            .mount(&server)
+            .await;
+        mock("GET", "threads/thread_1/messages")
+            .respond_with(ok(json!({
+                "object": "list",
+                "data": [message("msg_2", "assistant", "x = 1")],
+                "first_id": "msg_2",
+                "last_id": "msg_2",
+                "has_more": false,
+            })))
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let options = AssistantOptions {
+            name: Some("Math tutor".to_owned()),
+            instructions: Some("You are a math tutor.".to_owned()),
+            tools: vec![AssistantTool::CodeInterpreter],
+            ..Default::default()
+        };
+        let assistant = client.create_assistant(options, String::new()).await?;
+        assert_eq!(
+            assistant.tools,
+            [AssistantTool::CodeInterpreter, AssistantTool::Unknown]
+        );
+        let thread = client.create_thread(String::new()).await?;
+        let message = client
+            .add_message(&thread.id, "What is 3x + 11 = 14?", String::new())
+            .await?;
+        assert_eq!(message.text(), "What is 3x + 11 = 14?");
+        let run = client
+            .create_run(&thread.id, &assistant.id, String::new())
+            .await?;
+        assert_eq!(run.status, RunStatus::Queued);
+        let run = client
+            .poll_run(
+                &thread.id,
+                &run.id,
+                std::time::Duration::from_millis(10),
+                String::new(),
+            )
+            .await?;
+        assert_eq!(run.status, RunStatus::Completed);
+        let messages = client.list_messages(&thread.id, String::new()).await?;
+        assert_eq!(messages[0].text(), "x = 1");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_files() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        let file = json!({
+            "id": "file-abc123",
+            "object": "file",
+            "bytes": 26,
+            "created_at": 1699000000,
+            "filename": "requests.jsonl",
+            "purpose": "batch",
+        });
+        Mock::given(method("POST"))
+            .and(path("/v1/files"))
+            .and(|request: &wiremock::Request| {
+                let body = String::from_utf8_lossy(&request.body);
+                request.headers["Content-Type"]
+                    .to_str()
+                    .unwrap()
+                    .starts_with("multipart/form-data; boundary=")
+                    && body.contains("name=\"purpose\"\r\n\r\nbatch\r\n")
+                    && body.contains("filename=\"requests.jsonl\"")
+                    && body.contains(r#"{"custom_id":"request-1"}"#)
+            })
+            .respond_with(ResponseTemplate::new(200).set_body_json(file.clone()))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1/files"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "data": [file.clone()],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1/files/file-abc123"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/v1/files/file-abc123"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "file-abc123",
+                "object": "file",
+                "deleted": true,
+            })))
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let dir = std::env::temp_dir().join(format!("chatgpt-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let file_path = dir.join("requests.jsonl");
+        std::fs::write(&file_path, r#"{"custom_id":"request-1"}"#)?;
+        let uploaded = client
+            .upload_file(&file_path, FilePurpose::Batch, String::new())
+            .await;
+        std::fs::remove_dir_all(&dir)?;
+        let uploaded = uploaded?;
+        assert_eq!(
+            (uploaded.id.as_str(), uploaded.bytes, uploaded.purpose),
+            ("file-abc123", 26, FilePurpose::Batch)
+        );
+        assert_eq!(
+            client.list_files(String::new()).await?,
+            std::slice::from_ref(&uploaded)
+        );
+        assert_eq!(
+            client.retrieve_file("file-abc123", String::new()).await?,
+            uploaded
+        );
+        assert!(client.delete_file("file-abc123", String::new()).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_upload_retry() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(|request: &wiremock::Request| {
+                String::from_utf8_lossy(&request.body).contains(r#"{"custom_id":"request-1"}"#)
+            })
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "file-abc123",
+                "object": "file",
+                "bytes": 26,
+                "created_at": 1699000000,
+                "filename": "requests.jsonl",
+                "purpose": "batch",
+            })))
+            .mount(&server)
+            .await;
+
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        let options = ClientOptions::default()
+            .with_backend_api_url(url)
+            .with_max_retries(1);
+        let uploaded = ChatGPT::with_options("sk-test", options)?
+            .upload_file_bytes(
+                "requests.jsonl",
+                r#"{"custom_id":"request-1"}"#,
+                FilePurpose::Batch,
+                String::new(),
+            )
+            .await?;
+        assert_eq!(uploaded.id, "file-abc123");
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_transcription() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        let form_field = |body: &str, name: &str| {
+            body.split(&format!("name=\"{name}\"\r\n\r\n"))
+                .nth(1)
+                .and_then(|value| value.split("\r\n").next())
+                .map(str::to_owned)
+        };
+        Mock::given(method("POST"))
+            .and(path("/v1/audio/transcriptions"))
+            .and(move |request: &wiremock::Request| {
+                let body = String::from_utf8_lossy(&request.body);
+                form_field(&body, "model").as_deref() == Some("whisper-1")
+                    && form_field(&body, "language").as_deref() == Some("fr")
+                    && form_field(&body, "response_format").as_deref() == Some("verbose_json")
+                    && body.contains("filename=\"speech.mp3\"")
+            })
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "task": "transcribe",
+                "language": "french",
+                "duration": 1.5,
+                "text": "Bonjour !",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/audio/translations"))
+            .and(move |request: &wiremock::Request| {
+                let body = String::from_utf8_lossy(&request.body);
+                form_field(&body, "model").as_deref() == Some("whisper-1")
+                    && form_field(&body, "language").is_none()
+                    && form_field(&body, "prompt").as_deref() == Some("A greeting")
+                    && body.contains("filename=\"speech.mp3\"")
+            })
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "text": "Hello!" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let dir = std::env::temp_dir().join(format!("chatgpt-audio-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let file_path = dir.join("speech.mp3");
+        std::fs::write(&file_path, b"ID3")?;
+        let options = TranscriptionOptions {
+            language: Some("fr".to_owned()),
+            verbose: true,
+            ..Default::default()
+        };
+        let transcription = client
+            .transcribe(&file_path, options.clone(), String::new())
+            .await;
+        let translation = client
+            .translate_bytes(
+                "speech.mp3",
+                &b"ID3"[..],
+                TranscriptionOptions {
+                    prompt: Some("A greeting".to_owned()),
+                    verbose: false,
+                    ..options
+                },
+                String::new(),
+            )
+            .await;
+        std::fs::remove_dir_all(&dir)?;
+        let transcription = transcription?;
+        assert_eq!(transcription.text, "Bonjour !");
+        assert_eq!(transcription.language.as_deref(), Some("french"));
+        assert_eq!(transcription.duration, Some(1.5));
+        let translation = translation?;
+        assert_eq!(translation.text, "Hello!");
+        assert_eq!(translation.language, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_speech() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        let audio = b"ID3\x04\x00\x00\x00".to_vec();
+        Mock::given(method("POST"))
+            .and(path("/v1/audio/speech"))
+            .and(body_json(json!({
+                "model": "gpt-4o-mini-tts",
+                "input": "Hello!",
+                "voice": "nova",
+                "response_format": "mp3",
+                "speed": 1.5,
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(audio.clone(), "audio/mpeg"))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/audio/speech"))
+            .and(body_partial_json(json!({ "input": "" })))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "error": {
+                    "message": "Input must not be empty",
+                    "type": "invalid_request_error",
+                },
+            })))
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let options = SpeechOptions {
+            response_format: Some(SpeechFormat::Mp3),
+            speed: Some(1.5),
+            ..SpeechOptions::new(Voice::Nova)
+        };
+        let speech = client
+            .speech("Hello!", options.clone(), String::new())
+            .await?;
+        assert_eq!(speech.as_ref(), audio.as_slice());
+
+        let file_path =
+            std::env::temp_dir().join(format!("chatgpt-speech-{}.mp3", std::process::id()));
+        let written = client
+            .speech_to_file("Hello!", options.clone(), &file_path, String::new())
+            .await;
+        let read = std::fs::read(&file_path);
+        std::fs::remove_file(&file_path)?;
+        written?;
+        assert_eq!(read?, audio);
+
+        assert!(matches!(
+            client.speech("", options, String::new()).await,
+            Err(Error::ApiError { status, .. }) if status == 400
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_batches() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        let batch = |status: &str| {
+            json!({
+                "id": "batch_abc123",
+                "object": "batch",
+                "endpoint": "/v1/chat/completions",
+                "input_file_id": "file-abc123",
+                "completion_window": "24h",
+                "status": status,
+                "output_file_id": null,
+                "error_file_id": null,
+                "created_at": 1711471533,
+                "request_counts": { "total": 2, "completed": 1, "failed": 0 },
+                "metadata": null,
+            })
+        };
+        Mock::given(method("POST"))
+            .and(path("/v1/batches"))
+            .and(body_json(json!({
+                "input_file_id": "file-abc123",
+                "endpoint": "/v1/chat/completions",
+                "completion_window": "24h",
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(batch("validating")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1/batches/batch_abc123"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(batch("in_progress")))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/batches/batch_abc123/cancel"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(batch("cancelling")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let created = client
+            .create_batch(
+                "file-abc123",
+                CHAT_COMPLETIONS_ENDPOINT,
+                "24h",
+                String::new(),
+            )
+            .await?;
+        assert_eq!(
+            (created.id.as_str(), created.status),
+            ("batch_abc123", BatchStatus::Validating)
+        );
+        let retrieved = client.retrieve_batch("batch_abc123", String::new()).await?;
+        assert_eq!(retrieved.status, BatchStatus::InProgress);
+        assert_eq!(
+            (
+                retrieved.request_counts.total,
+                retrieved.request_counts.completed
+            ),
+            (2, 1)
+        );
+        let cancelled = client.cancel_batch("batch_abc123", String::new()).await?;
+        assert_eq!(cancelled.status, BatchStatus::Cancelling);
+        assert!(!cancelled.status.is_terminal());
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_input() -> crate::Result<()> {
+        let client = ChatGPT::new("sk-test")?;
+        let input = client.batch_input(vec![
+            (
+                "request-1".to_owned(),
+                user_message("Hello"),
+                CompletionOptions::default(),
+            ),
+            (
+                "request-2".to_owned(),
+                user_message("Hi"),
+                CompletionOptions {
+                    model: Some(Model::Gpt4o),
+                    stream: Some(true),
+                    ..Default::default()
+                },
+            ),
+        ])?;
+        let lines = input
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<serde_json::Value>, _>>()?;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["custom_id"], "request-1");
+        assert_eq!(lines[0]["method"], "POST");
+        assert_eq!(lines[0]["url"], "/v1/chat/completions");
+        assert_eq!(
+            lines[0]["body"]["model"],
+            serde_json::to_value(ClientOptions::default().default_model)?
+        );
+        assert_eq!(lines[0]["body"]["messages"][0]["content"], "Hello");
+        assert_eq!(lines[1]["body"]["model"], "gpt-4o");
+        assert!(lines[1]["body"].get("stream").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_custom_headers() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(header("X-Trace-Id", "trace-123"))
+            .and(header("X-Title", "gateway"))
+            .and(header("Authorization", "Bearer sk-test"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hello there!" },
+                }],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 12, "total_tokens": 21 },
+            })))
+            .mount(&server)
+            .await;
 
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Trace-Id", HeaderValue::from_static("trace-123"));
+        headers.insert("X-Title", HeaderValue::from_static("gateway"));
+        let response = mock_client(&server)
+            .with_headers(headers)
+            .send_message("Hello", Default::default(), String::new())
+            .await?;
+        assert_eq!(response, "Hello there!");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_no_organization() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [],
+            })))
+            .mount(&server)
+            .await;
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        let options = ClientOptions::default()
+            .with_backend_api_url(url)
+            .with_organization("org-global");
+        let client = ChatGPT::with_options("sk-test", options)?;
+        for org in [
+            Organization::Default,
+            Organization::None,
+            Organization::Id("org-request".to_owned()),
+        ] {
+            client
+                .send_message_full("Hello", Default::default(), org)
+                .await?;
+        }
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests[0].headers["OpenAI-Organization"], "org-global");
+        assert!(requests[1].headers.get("OpenAI-Organization").is_none());
+        assert_eq!(requests[2].headers["OpenAI-Organization"], "org-request");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_project_header() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 0, "total_tokens": 9 },
+            })))
+            .mount(&server)
+            .await;
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        let options = ClientOptions::default()
+            .with_backend_api_url(url)
+            .with_project(Some("proj-test".to_owned()));
+        let client = ChatGPT::with_options("sk-test", options)?;
+        client
+            .send_message_full("Hello", Default::default(), "org-test".to_owned())
+            .await?;
+        mock_client(&server)
+            .send_message_full("Hello", Default::default(), String::new())
+            .await?;
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests[0].headers["OpenAI-Project"], "proj-test");
+        assert_eq!(requests[0].headers["OpenAI-Organization"], "org-test");
+        assert!(requests[1].headers.get("OpenAI-Project").is_none());
+        assert!(requests[1].headers.get("OpenAI-Organization").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_user_agent() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [],
+            })))
+            .mount(&server)
+            .await;
+        mock_client(&server)
+            .send_message_full("Hello", Default::default(), String::new())
+            .await?;
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        let options = ClientOptions::default()
+            .with_backend_api_url(url)
+            .with_user_agent("my-app/1.0");
+        ChatGPT::with_options("sk-test", options)?
+            .send_message_full("Hello", Default::default(), String::new())
+            .await?;
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(
+            requests[0].headers["User-Agent"],
+            concat!("chatgpt_rs/", env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(requests[1].headers["User-Agent"], "my-app/1.0");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_embeddings() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .and(body_partial_json(json!({
+                "model": "text-embedding-3-small",
+                "encoding_format": "base64",
+                "input": ["Hello", "World"],
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "data": [
+                    // 1.0 and -2.0 as little-endian f32s
+                    { "object": "embedding", "index": 0, "embedding": "AACAPwAAAMA=" },
+                    { "object": "embedding", "index": 1, "embedding": "AAAAAA==" },
+                ],
+                "model": "text-embedding-3-small",
+                "usage": { "prompt_tokens": 2, "total_tokens": 2 },
+            })))
+            .mount(&server)
+            .await;
+
+        let options = EmbeddingOptions {
+            encoding_format: Some(EncodingFormat::Base64),
+            ..Default::default()
+        };
+        let response = mock_client(&server)
+            .embeddings(["Hello", "World"], options, String::new())
+            .await?;
+        assert_eq!(response.data[0].embedding, [1.0, -2.0]);
+        assert_eq!(response.data[1].embedding, [0.0]);
+        assert_eq!(response.usage.total_tokens, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_adaptive_throttling() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("x-ratelimit-limit-requests", "100")
+                    .insert_header("x-ratelimit-remaining-requests", "0")
+                    .insert_header("x-ratelimit-reset-requests", "300ms")
+                    .set_body_json(json!({
+                        "id": "chatcmpl-123",
+                        "object": "chat.completion",
+                        "created": 1677652288,
+                        "choices": [],
+                        "usage": { "prompt_tokens": 9, "completion_tokens": 0, "total_tokens": 9 },
+                    })),
+            )
+            .mount(&server)
+            .await;
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+
+        let unthrottled = mock_client(&server);
+        unthrottled
+            .send_message_full("Hello", Default::default(), String::new())
+            .await?;
+        assert_eq!(
+            unthrottled.rate_limits().unwrap().remaining_requests,
+            Some(0)
+        );
+        let started = std::time::Instant::now();
+        unthrottled
+            .send_message_full("Hello", Default::default(), String::new())
+            .await?;
+        assert!(started.elapsed() < std::time::Duration::from_millis(300));
+
+        let options = ClientOptions::default()
+            .with_backend_api_url(url)
+            .with_adaptive_throttling(true);
+        let throttled = ChatGPT::with_options("sk-test", options)?;
+        throttled
+            .send_message_full("Hello", Default::default(), String::new())
+            .await?;
+        let started = std::time::Instant::now();
+        throttled
+            .send_message_full("Hello", Default::default(), String::new())
+            .await?;
+        assert!(started.elapsed() >= std::time::Duration::from_millis(250));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_send_messages() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({
+                "messages": [
+                    { "role": "user", "content": "Hello" },
+                    { "role": "user", "content": "How are you?" },
+                ],
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Fine!" },
+                }],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 2, "total_tokens": 11 },
+            })))
+            .mount(&server)
+            .await;
+
+        let history = ["Hello", "", "How are you?"];
+        let response = mock_client(&server)
+            .send_messages(
+                history
+                    .iter()
+                    .filter(|content| !content.is_empty())
+                    .copied(),
+                Default::default(),
+                String::new(),
+            )
+            .await?;
+        assert_eq!(response, "Fine!");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_message_with_model() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        for model in ["gpt-4o", "o3-mini"] {
+            Mock::given(method("POST"))
+                .and(body_partial_json(json!({ "model": model })))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "id": "chatcmpl-123",
+                    "object": "chat.completion",
+                    "created": 1677652288,
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": model },
+                    }],
+                    "usage": { "prompt_tokens": 9, "completion_tokens": 2, "total_tokens": 11 },
+                })))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let client = mock_client(&server);
+        let options = CompletionOptions {
+            model: Some(Model::Gpt4oMini),
+            ..Default::default()
+        };
+        for model in [Model::Gpt4o, Model::O3Mini] {
+            let response = client
+                .send_message_with_model(model.clone(), "Hello", &options, String::new())
+                .await?;
+            assert_eq!(response, model.id());
+        }
+        assert_eq!(options.model, Some(Model::Gpt4oMini));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_batch() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        for (input, status) in [("one", 200), ("two", 500), ("three", 200)] {
+            Mock::given(method("POST"))
+                .and(body_partial_json(json!({
+                    "messages": [{ "role": "user", "content": input }],
+                })))
+                .respond_with(
+                    ResponseTemplate::new(status)
+                        .set_delay(std::time::Duration::from_millis(50))
+                        .set_body_json(json!({
+                            "id": input,
+                            "object": "chat.completion",
+                            "created": 1677652288,
+                            "choices": [],
+                        })),
+                )
+                .mount(&server)
+                .await;
+        }
+
+        let client = mock_client(&server);
+        let requests = ["one", "two", "three"]
+            .map(|input| (vec![Message::from(input)], CompletionOptions::default()))
+            .to_vec();
+        let started = std::time::Instant::now();
+        let mut results: Vec<_> = client
+            .send_batch(requests, 3, String::new())
+            .collect()
+            .await;
+        assert!(started.elapsed() < std::time::Duration::from_millis(140));
+        results.sort_by_key(|(index, _)| *index);
+        assert_eq!(results[0].1.as_ref().unwrap().id, "one");
+        assert!(results[1].1.is_err());
+        assert_eq!(results[2].1.as_ref().unwrap().id, "three");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_summarize() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({
+                "model": "gpt-4o-mini",
+                "messages": [
+                    { "role": "system" },
+                    { "role": "user", "content": "user: My name is Ada\nassistant: Hi Ada!" },
+                ],
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "The user is called Ada." },
+                }],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 6, "total_tokens": 15 },
+            })))
+            .mount(&server)
+            .await;
+
+        let system = Message {
+            role: "system".to_owned(),
+            content: Some("Be brief".into()),
+            ..Default::default()
+        };
+        let history = vec![
+            system.clone(),
+            Message::from("My name is Ada"),
+            Message::from((Role::Assistant, "Hi Ada!")),
+            Message::from("What's my name?"),
+        ];
+        let mut conversation = mock_client(&server).new_conversation(String::new());
+        conversation.history = history;
+        conversation.summary_model = Some(Model::Gpt4oMini);
+        let summary = conversation.summarize_older_than(1).await?;
+        assert_eq!(summary.as_deref(), Some("The user is called Ada."));
+        assert_eq!(conversation.history.len(), 3);
+        assert_eq!(conversation.history[0], system);
+        assert_eq!(
+            conversation.history[1].content,
+            Some("Summary of the earlier conversation: The user is called Ada.".into())
+        );
+        assert_eq!(
+            conversation.history[2].content,
+            Some("What's my name?".into())
+        );
+        assert_eq!(conversation.summarize_older_than(1).await?, None);
+
+        conversation.set_system("Be funny");
+        assert_eq!(conversation.history.len(), 3);
+        assert_eq!(conversation.history[0].content, Some("Be funny".into()));
+        assert_eq!(
+            conversation.history[1].content,
+            Some("Summary of the earlier conversation: The user is called Ada.".into())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_branch() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Sure!" },
+                }],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 2, "total_tokens": 11 },
+            })))
+            .mount(&server)
+            .await;
+
+        let mut conversation = mock_client(&server).new_conversation(String::new());
+        conversation.send_message("Tell me a story").await?;
+        let mut branch = conversation.branch();
+        branch.options.temperature = Some(1.5);
+        branch.send_message("Make it funnier").await?;
+        assert_eq!(branch.history.len(), 4);
+        assert_eq!(conversation.history.len(), 2);
+        assert_eq!(conversation.options.temperature, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_system() -> crate::Result<()> {
+        let client = ChatGPT::new("sk-test")?;
+        let history = vec![
+            Message::from("Hello"),
+            Message::from((Role::Assistant, "Hi!")),
+        ];
+        let mut conversation = Conversation::new_with_history(client, history, String::new());
+
+        conversation.set_system("You are a pirate.");
+        conversation.set_system("You are a poet.");
+        let roles: Vec<_> = conversation.history.iter().map(Message::role).collect();
+        assert_eq!(
+            roles,
+            [Some(Role::System), Some(Role::User), Some(Role::Assistant)]
+        );
+        assert_eq!(
+            conversation.history[0].content,
+            Some("You are a poet.".into())
+        );
+        assert_eq!(conversation.history[1].content, Some("Hello".into()));
+
+        conversation.history[0] = Message::from((Role::Developer, "Answer briefly."));
+        conversation
+            .history
+            .push(Message::from((Role::System, "Answer in French.")));
+        conversation.set_system("Answer in detail.");
+        assert_eq!(conversation.history.len(), 3);
+        assert_eq!(conversation.history[0].role(), Some(Role::Developer));
+        assert_eq!(
+            conversation.history[0].content,
+            Some("Answer in detail.".into())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_conversation_stream() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        let chunk = |delta: serde_json::Value| {
+            json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion.chunk",
+                "created": 1677652288,
+                "choices": [{ "index": 0, "delta": delta }],
+            })
+        };
+        let body = format!(
+            "data: {}\n\ndata: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            chunk(json!({ "role": "assistant" })),
+            chunk(json!({ "content": "Hello" })),
+            chunk(json!({ "content": " there!" })),
+        );
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({
+                "stream": true,
+                "messages": [{ "role": "user", "content": "Hello" }],
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        // Cut off before [DONE]
+        let body = format!("data: {}\n\n", chunk(json!({ "content": "Good" })));
+        Mock::given(method("POST"))
+            .and(|request: &wiremock::Request| {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                body["messages"][2] == json!({ "role": "user", "content": "Bye" })
+            })
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let mut conversation = Conversation::new(mock_client(&server), String::new());
+        let deltas: Vec<String> = conversation
+            .stream("Hello")
+            .await?
+            .map(|delta| delta.unwrap())
+            .collect()
+            .await;
+        assert_eq!(deltas, ["Hello", " there!"]);
+        assert_eq!(
+            conversation.history,
+            [
+                Message::from("Hello"),
+                Message::from((Role::Assistant, "Hello there!"))
+            ]
+        );
+
+        let deltas: Vec<crate::Result<String>> = conversation.stream("Bye").await?.collect().await;
+        assert_eq!(deltas.len(), 2);
+        assert_eq!(deltas[0].as_deref().ok(), Some("Good"));
+        assert!(matches!(deltas[1], Err(Error::BackendError(_))));
+        assert_eq!(conversation.history.len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_edit_and_resend() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({
+                "messages": [
+                    { "role": "user", "content": "What is 2 + 3?" },
+                ],
+            })))
+            .and(|request: &wiremock::Request| {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                body["messages"].as_array().unwrap().len() == 1
+            })
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "5" },
+                }],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let tool_calls: Message = serde_json::from_value(json!({
+            "role": "assistant",
+            "tool_calls": [
+                { "id": "call_1", "type": "function", "function": { "name": "add", "arguments": "{}" } },
+                { "id": "call_2", "type": "function", "function": { "name": "add", "arguments": "{}" } },
+            ],
+        }))?;
+        let history = vec![
+            Message::from("What is 2 + 2?"),
+            tool_calls,
+            Message::tool_result("call_1", "4"),
+            Message::tool_result("call_2", "4"),
+            Message::from((Role::Assistant, "4")),
+            Message::from("Thanks!"),
+        ];
+        let mut conversation =
+            Conversation::new_with_history(mock_client(&server), history, String::new());
+        assert_eq!(conversation.messages_since(4).len(), 2);
+        assert!(conversation.messages_since(7).is_empty());
+
+        for index in [2, 3, 7] {
+            assert!(matches!(
+                conversation.truncate_to(index),
+                Err(Error::InvalidRequest(_))
+            ));
+        }
+        assert!(conversation.edit_message(2, "5").is_err());
+        assert!(conversation.edit_message(6, "Hello").is_err());
+        assert!(conversation.resend_from(4).await.is_err());
+        assert_eq!(conversation.history.len(), 6);
+
+        conversation.truncate_to(5)?;
+        assert_eq!(conversation.history.len(), 5);
+        conversation.edit_message(0, "What is 2 + 3?")?;
+        assert_eq!(conversation.resend_from(0).await?, "5");
+        let contents: Vec<_> = conversation
+            .history
+            .iter()
+            .map(|message| message.content.clone())
+            .collect();
+        assert_eq!(contents, [Some("What is 2 + 3?".into()), Some("5".into())]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_instruction_role() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        for (model, role) in [
+            ("gpt-4o", "system"),
+            ("o3-mini", "developer"),
+            ("gpt-4o-mini", "developer"),
+        ] {
+            Mock::given(method("POST"))
+                .and(body_partial_json(json!({
+                    "model": model,
+                    "messages": [{ "role": role, "content": "Answer briefly." }],
+                })))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "id": "chatcmpl-123",
+                    "object": "chat.completion",
+                    "created": 1677652288,
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": "Hi!" },
+                    }],
+                })))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let mut conversation = mock_client(&server).new_conversation(String::new());
+        conversation.set_system("Answer briefly.");
+        for model in [Model::Gpt4o, Model::O3Mini] {
+            let mut branch = conversation.branch();
+            branch.options.model = Some(model);
+            branch.send_message("Hello").await?;
+            assert_eq!(branch.history[0].role(), Some(Role::System));
+        }
+        conversation.instruction_role = Some(Role::Developer);
+        conversation.send_message("Hello").await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_regenerate() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        for (temperature, content) in [(json!(null), "A dog"), (json!(1.5), "A cat")] {
+            Mock::given(method("POST"))
+                .and(body_partial_json(json!({
+                    "messages": [{ "role": "user", "content": "Name an animal" }],
+                })))
+                .and(move |request: &wiremock::Request| {
+                    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                    body["messages"].as_array().unwrap().len() == 1
+                        && body.get("temperature").cloned().unwrap_or_default() == temperature
+                })
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "id": "chatcmpl-123",
+                    "object": "chat.completion",
+                    "created": 1677652288,
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": content },
+                    }],
+                })))
+                .mount(&server)
+                .await;
+        }
+
+        let mut conversation = mock_client(&server).new_conversation(String::new());
+        assert!(conversation.regenerate().await.is_err());
+        assert_eq!(conversation.send_message("Name an animal").await?, "A dog");
+        let options = CompletionOptions {
+            temperature: Some(1.5),
+            ..Default::default()
+        };
+        assert_eq!(conversation.regenerate_with(options).await?, "A cat");
+        assert_eq!(conversation.history.len(), 2);
+        assert_eq!(conversation.history[1].content, Some("A cat".into()));
+
+        conversation.history.push(Message::from("Another one"));
+        assert!(matches!(
+            conversation.regenerate().await,
+            Err(Error::InvalidRequest(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_token_usage() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hi!" },
+                }],
+                "usage": {
+                    "prompt_tokens": 9,
+                    "completion_tokens": 3,
+                    "total_tokens": 12,
+                    "completion_tokens_details": { "reasoning_tokens": 1 },
+                },
+            })))
+            .mount(&server)
+            .await;
+
+        let mut conversation = mock_client(&server).new_conversation(String::new());
+        conversation.send_message("Hello").await?;
+        conversation.regenerate().await?;
+        let usage = conversation.token_usage();
+        assert_eq!(
+            (
+                usage.prompt_tokens,
+                usage.completion_tokens,
+                usage.total_tokens
+            ),
+            (18, 6, 24)
+        );
+        assert_eq!(
+            usage
+                .completion_tokens_details
+                .as_ref()
+                .unwrap()
+                .reasoning_tokens,
+            Some(2)
+        );
+        assert_eq!(usage.prompt_tokens_details, None);
+        assert_eq!(conversation.branch().token_usage().total_tokens, 24);
+
+        conversation.reset_token_usage();
+        assert_eq!(conversation.token_usage(), &Usage::default());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_continue_response() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        let replies = [
+            ("Count to five", "1, 2,", "length"),
+            ("1, 2,", " 3, 4,", "length"),
+            ("1, 2, 3, 4,", " 5.", "stop"),
+        ];
+        for (last, content, finish_reason) in replies {
+            Mock::given(method("POST"))
+                .and(move |request: &wiremock::Request| {
+                    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                    let messages = body["messages"].as_array().unwrap();
+                    let continued = messages.len() == 3 && messages[1]["content"] == last;
+                    continued || (messages.len() == 1 && messages[0]["content"] == last)
+                })
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "id": "chatcmpl-123",
+                    "object": "chat.completion",
+                    "created": 1677652288,
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": content },
+                        "finish_reason": finish_reason,
+                    }],
+                })))
+                .mount(&server)
+                .await;
+        }
+
+        let mut conversation = mock_client(&server).new_conversation(String::new());
+        assert_eq!(conversation.send_message("Count to five").await?, "1, 2,");
+        assert_eq!(conversation.continue_response(1).await?, "1, 2, 3, 4,");
+        assert_eq!(conversation.continue_response(5).await?, "1, 2, 3, 4, 5.");
+        assert_eq!(conversation.history.len(), 2);
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+
+        // The response is complete, there is nothing to continue
+        assert!(matches!(
+            conversation.continue_response(1).await,
+            Err(Error::InvalidRequest(_))
+        ));
+        assert_eq!(
+            conversation.history[1].content,
+            Some("1, 2, 3, 4, 5.".into())
+        );
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_message_without_choices() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "choices": [] })))
+            .mount(&server)
+            .await;
+
+        let result = mock_client(&server)
+            .send_message("Hello", Default::default(), String::new())
+            .await;
+        assert!(matches!(result, Err(Error::BackendError(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_message_raw() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        let body = json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1677652288,
+            "choices": [],
+            "usage": { "prompt_tokens": 9, "completion_tokens": 0, "total_tokens": 9 },
+            "unmodeled_field": { "nested": true },
+        });
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body.clone()))
+            .mount(&server)
+            .await;
+
+        let raw = mock_client(&server)
+            .send_message_raw("Hello", Default::default(), String::new())
+            .await?;
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&raw)?, body);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_openrouter() -> crate::Result<()> {
+        let options = ClientOptions::openrouter("https://example.com", "Example");
+        assert_eq!(
+            options.chat_completions_url()?.as_str(),
+            "https://openrouter.ai/api/v1/chat/completions"
+        );
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("HTTP-Referer", "https://example.com"))
+            .and(header("X-Title", "Example"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "gen-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "provider": "Anthropic",
+                "choices": [],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 0, "total_tokens": 9 },
+            })))
+            .mount(&server)
+            .await;
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        let response = ChatGPT::with_options("sk-or-test", options.with_backend_api_url(url))?
+            .send_message_full("Hello", Default::default(), String::new())
+            .await?;
+        assert_eq!(response.provider.as_deref(), Some("Anthropic"));
+
+        mock_client(&server)
+            .send_message_full("Hello", Default::default(), String::new())
+            .await
+            .ok();
+        let requests = server.received_requests().await.unwrap();
+        assert!(requests[1].headers.get("HTTP-Referer").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_parse_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": 42 },
+                }],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 2, "total_tokens": 11 },
+            })))
+            .mount(&server)
+            .await;
+
+        let error = mock_client(&server)
+            .send_message_full(user_message("Hello"), Default::default(), String::new())
+            .await
+            .unwrap_err();
+        match &error {
+            Error::ApiError {
+                path: Some(path), ..
+            } => assert_eq!(path, "choices[0].message.content"),
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+        let message = error.to_string();
+        assert!(message.contains("`choices[0].message.content`"));
+        assert!(message.contains("chatcmpl-123"));
+    }
+
+    #[tokio::test]
+    async fn test_mock_retries() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(
+                json!({ "messages": [{ "content": "Hello" }] }),
+            ))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(
+                json!({ "messages": [{ "content": "Hello" }] }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hello there!" },
+                }],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 3, "total_tokens": 12 },
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(
+                json!({ "messages": [{ "content": "Invalid" }] }),
+            ))
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        let client = ChatGPT::with_options(
+            "sk-test",
+            ClientOptions::default()
+                .with_backend_api_url(url)
+                .with_max_retries(2),
+        )?;
+        let response = client
+            .send_message("Hello", Default::default(), String::new())
+            .await?;
+        assert_eq!(response, "Hello there!");
+        let error = client
+            .send_message("Invalid", Default::default(), String::new())
+            .await
+            .unwrap_err();
+        assert!(!error.is_retryable());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_timeout() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(2)))
+            .mount(&server)
+            .await;
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        let options = ClientOptions::default()
+            .with_backend_api_url(url)
+            .with_timeout(std::time::Duration::from_millis(100));
+        let error = ChatGPT::with_options("sk-test", options)?
+            .send_message("Hello", Default::default(), String::new())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::Timeout(_)), "{error:?}");
+        assert!(error.is_retryable());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_local_server() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "model": "llama3.2",
+                "choices": [{
+                    "message": { "role": "assistant", "content": "Hello!" },
+                    "finish_reason": "stop",
+                }],
+            })))
+            .mount(&server)
+            .await;
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        let options = ClientOptions::default()
+            .with_backend_api_url(url)
+            .with_default_model("llama3.2");
+        let response = ChatGPT::with_options("", options)?
+            .send_message_full("Hello", Default::default(), String::new())
+            .await?;
+        assert_eq!(response.choices[0].message.content, Some("Hello!".into()));
+        assert_eq!(response.usage.total_tokens, 0);
+
+        let requests = server.received_requests().await.unwrap();
+        assert!(requests[0].headers.get("Authorization").is_none());
+        assert!(requests[0].headers.get("api-key").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_interceptors() -> crate::Result<()> {
+        struct Tracer(AtomicUsize);
+        impl Interceptor for Tracer {
+            fn on_request<'a>(
+                &'a self,
+                request: &'a mut http::request::Parts,
+            ) -> InterceptorFuture<'a> {
+                Box::pin(async move {
+                    tokio::task::yield_now().await;
+                    request
+                        .headers
+                        .insert("X-Trace-Id", HeaderValue::from_static("trace-1"));
+                })
+            }
+
+            fn on_response<'a>(
+                &'a self,
+                response: &'a http::response::Parts,
+            ) -> InterceptorFuture<'a> {
+                Box::pin(async move {
+                    assert_eq!(response.status, StatusCode::OK);
+                    self.0.fetch_add(1, Ordering::SeqCst);
+                })
+            }
+        }
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("X-Trace-Id", "trace-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [],
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+        let tracer = Arc::new(Tracer(AtomicUsize::new(0)));
+        let url = Url::parse(&format!("{}/v1/", server.uri())).unwrap();
+        let options = ClientOptions::default()
+            .with_backend_api_url(url)
+            .with_interceptor(tracer.clone());
+        let client = ChatGPT::with_options("sk-test", options)?;
+        client
+            .send_message_full("Hello", Default::default(), String::new())
+            .await?;
+        client
+            .send_message_streaming("Hello", Default::default(), String::new())
+            .await?
+            .count()
+            .await;
+        assert_eq!(tracer.0.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_shutdown() -> crate::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1677652288,
+                "choices": [{ "index": 0, "message": { "role": "assistant", "content": "Hi!" } }],
+            })))
+            .mount(&server)
+            .await;
+        let metrics = tokio::runtime::Handle::current().metrics();
+        let tasks = metrics.num_alive_tasks();
+        let client = mock_client(&server);
+        let mut conversation = client.new_conversation(String::new());
+        conversation.send_message("Hello").await?;
+        client
+            .send_message_streaming("Hello", Default::default(), String::new())
+            .await?
+            .next()
+            .await;
+        assert!(metrics.num_alive_tasks() > tasks);
+        drop(conversation);
+        client.shutdown().await;
+        // The connections end once the runtime gets to their tasks, with no copy of the client or stream left
+        for _ in 0..100 {
+            if metrics.num_alive_tasks() == tasks {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(metrics.num_alive_tasks(), tasks);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_api_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(429).set_body_json(json!({
+                "error": { "message": "Rate limit reached", "type": "requests" },
+            })))
+            .mount(&server)
+            .await;
+
+        let result = mock_client(&server)
+            .send_message_full(user_message("Hello"), Default::default(), String::new())
+            .await;
+        match result {
+            Err(Error::ApiError {
+                status,
+                error: Some(error),
+                ..
+            }) => {
+                assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+                assert_eq!(error.message, "Rate limit reached");
+            }
+            other => panic!("expected an API error, got {other:?}"),
+        }
+    }
 }
@@ -1,7 +1,18 @@
+use crate::conversation::Conversation;
+use crate::interceptor::{Interceptor, Interceptors};
+use crate::ratelimit::{RateLimits, Throttle};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::stream::ChatStream;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::transport::{HttpClient, Transport};
 use crate::types::{
-    ChatCompletionChunk, CompletionOptions, ConversationResponse, Message, ResponsePart,
+    validate_messages, ApiErrorEnvelope, ChatCompletionChunk, CompletionOptions, Content,
+    ConversationResponse, EmbeddingOptions, EmbeddingResponse, IntoMessages, Message, Model,
+    ResponseFormat, ResponsePart, StreamOptions, TextCompletionResponse,
 };
-use eventsource_stream::{EventStream, Eventsource};
+use eventsource_stream::{Event, EventStream, EventStreamError, Eventsource};
+use futures_util::future;
+use futures_util::stream;
 use futures_util::Stream;
 use futures_util::StreamExt;
 use json_value_merge::Merge;
@@ -9,28 +20,457 @@ use reqwest::{
     header::{HeaderMap, HeaderValue, USER_AGENT},
     Method, Url,
 };
+use serde::de::DeserializeOwned;
 use serde_json::json;
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::HashSet;
+use std::future::Future;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
+/// The number of times [`ChatGPT::shutdown`] yields to the runtime, which usually lets the tasks of idle connections end
+#[cfg(not(target_arch = "wasm32"))]
+const SHUTDOWN_YIELDS: usize = 4;
+
+/// The environment variable the API key is read from by default
+const DEFAULT_API_KEY_VAR: &str = "OPENAI_API_KEY";
+
+/// The `User-Agent` header sent when none is configured
+const DEFAULT_USER_AGENT: &str = concat!("chatgpt_rs/", env!("CARGO_PKG_VERSION"));
+
+/// The delay before the first retry of a failed request, doubled before every next one
+#[cfg(not(target_arch = "wasm32"))]
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Configuration of an Azure OpenAI deployment
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct AzureConfig {
+    /// Name of the Azure OpenAI resource, e.g. `my-resource` for `https://my-resource.openai.azure.com`
+    pub resource: String,
+    /// Name of the model deployment to send requests to
+    pub deployment: String,
+    /// Version of the API, sent as the `api-version` query parameter, e.g. `2024-02-01`
+    pub api_version: String,
+}
+
+impl AzureConfig {
+    /// Constructs a new Azure configuration
+    pub fn new<S: Into<String>>(resource: S, deployment: S, api_version: S) -> Self {
+        Self {
+            resource: resource.into(),
+            deployment: deployment.into(),
+            api_version: api_version.into(),
+        }
+    }
+
+    /// Builds the chat completions url of this deployment
+    pub fn chat_completions_url(&self) -> crate::Result<Url> {
+        self.endpoint("chat/completions")
+    }
+
+    /// Builds the url of an endpoint of this deployment, e.g. `completions`
+    pub fn endpoint(&self, path: &str) -> crate::Result<Url> {
+        let mut url = Url::parse(&format!(
+            "https://{}.openai.azure.com/openai/deployments/{}/{}",
+            self.resource, self.deployment, path
+        ))
+        .map_err(|err| crate::err::Error::ParsingError(err.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("api-version", &self.api_version);
+        Ok(url)
+    }
+}
+
+/// Configuration of an HTTP or HTTPS proxy the requests are routed through
+#[derive(Clone, PartialEq, PartialOrd)]
+pub struct ProxyConfig {
+    /// Url of the proxy, e.g. `http://proxy.example.com:8080`
+    pub url: Url,
+    /// Username and password sent to the proxy with basic authentication
+    pub basic_auth: Option<(String, String)>,
+}
+
+impl ProxyConfig {
+    /// Constructs a new proxy configuration, without authentication
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            basic_auth: None,
+        }
+    }
+
+    /// Authenticates to the proxy with basic authentication
+    pub fn with_basic_auth<S: Into<String>>(mut self, username: S, password: S) -> Self {
+        self.basic_auth = Some((username.into(), password.into()));
+        self
+    }
+}
+
+impl std::fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("url", &self.url.as_str())
+            .field(
+                "basic_auth",
+                &self
+                    .basic_auth
+                    .as_ref()
+                    .map(|(username, _)| (username, "<redacted>")),
+            )
+            .finish()
+    }
+}
+
+/// The organization a request is sent for, in the `OpenAI-Organization` header.
+///
+/// Converted from the `org` of a request, where an empty string stands for [`Organization::Default`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum Organization {
+    /// The organization set with [`ClientOptions::with_organization`], if any
+    #[default]
+    Default,
+    /// No organization, even when [`ClientOptions::with_organization`] is set, e.g. for proxies rejecting the header
+    None,
+    /// The given organization, overriding the one of the client options
+    Id(String),
+}
+
+impl From<String> for Organization {
+    fn from(org: String) -> Self {
+        if org.is_empty() {
+            Self::Default
+        } else {
+            Self::Id(org)
+        }
+    }
+}
+
+impl From<&str> for Organization {
+    fn from(org: &str) -> Self {
+        org.to_owned().into()
+    }
+}
+
 /// Options for the ChatGPT client
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct ClientOptions {
     backend_api_url: Url,
+    api_url: Option<Url>,
+    azure: Option<AzureConfig>,
+    pool_max_idle_per_host: usize,
+    pool_idle_timeout: Option<Duration>,
+    http2_prior_knowledge: bool,
+    proxy: Option<ProxyConfig>,
+    stream_idle_timeout: Option<Duration>,
+    first_token_timeout: Option<Duration>,
+    stream_connect_deadline: Option<Duration>,
+    max_stream_resumptions: usize,
+    compression: bool,
+    adaptive_throttling: bool,
+    max_retries: usize,
+    validate_messages: bool,
+    validate_requests: bool,
+    strict_sampling: bool,
+    timeout: Option<Duration>,
+    organization: Option<String>,
+    project: Option<String>,
+    referer: Option<String>,
+    title: Option<String>,
+    user_agent: Option<String>,
+    interceptors: Interceptors,
+    #[cfg(not(target_arch = "wasm32"))]
+    http_client: Option<Transport>,
+    pub(crate) default_model: Model,
 }
 
 impl ClientOptions {
-    /// Sets the default backend API url. This is different from [`Self::with_api_url`] and defaults to https://chat.openai.com/backend-api
+    /// Options to send requests to [OpenRouter](https://openrouter.ai), which routes them to the providers of many models.
+    ///
+    /// The referer and title are sent in the `HTTP-Referer` and `X-Title` headers, which OpenRouter uses to attribute the requests to your app.
+    /// Use an OpenRouter API key with it, and model ids such as `openai/gpt-4o`.
+    pub fn openrouter<S: Into<String>>(referer: S, title: S) -> Self {
+        Self::default()
+            .with_backend_api_url(Url::from_str("https://openrouter.ai/api/v1/").unwrap())
+            .with_attribution(referer, title)
+    }
+
+    /// Sets the `HTTP-Referer` and `X-Title` headers attributing the requests to your app, which are not sent by default
+    pub fn with_attribution<S: Into<String>>(mut self, referer: S, title: S) -> Self {
+        self.referer = Some(referer.into());
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Sets the base url of the API, which defaults to https://api.openai.com/v1/
+    ///
+    /// Endpoints are resolved relative to it, e.g. chat completions are sent to `<backend_api_url>/chat/completions`.
+    /// To point the chat completions at an arbitrary url instead, use [`Self::with_api_url`].
     pub fn with_backend_api_url(mut self, backend_url: Url) -> Self {
         self.backend_api_url = backend_url;
         self
     }
+
+    /// Sets the full url of the chat completions endpoint, e.g. https://api.openai.com/v1/chat/completions
+    ///
+    /// This takes precedence over both [`Self::with_backend_api_url`] and [`Self::with_azure`] for chat completions.
+    pub fn with_api_url(mut self, api_url: Url) -> Self {
+        self.api_url = Some(api_url);
+        self
+    }
+
+    /// Routes all requests to an Azure OpenAI deployment.
+    ///
+    /// The url is built from the resource and deployment names, and the API key is sent in the `api-key` header instead of `Authorization: Bearer`.
+    /// The backend API url is ignored while this is set.
+    pub fn with_azure(mut self, azure: AzureConfig) -> Self {
+        self.azure = Some(azure);
+        self
+    }
+
+    /// Routes all requests through an HTTP or HTTPS proxy.
+    ///
+    /// Without it, the proxies of the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables are used, which also
+    /// covers setups such as a proxy per scheme. It is ignored on `wasm32`, where the browser handles proxies itself
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Sets the maximum number of idle connections kept alive per host, defaults to no limit.
+    ///
+    /// Like the other connection options, it is ignored on `wasm32`, where the browser manages the connections
+    pub fn with_pool_max_idle_per_host(mut self, max_idle: usize) -> Self {
+        self.pool_max_idle_per_host = max_idle;
+        self
+    }
+
+    /// Sets how long idle connections are kept alive, defaults to 90 seconds. `None` keeps them alive forever
+    pub fn with_pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.pool_idle_timeout = timeout;
+        self
+    }
+
+    /// Only speaks HTTP/2 with the backend, without negotiating it first.
+    ///
+    /// By default, HTTP/2 is negotiated over TLS when the backend supports it, so that concurrent requests are multiplexed over a single connection.
+    /// Enable this for backends known to speak HTTP/2 over plain HTTP.
+    pub fn with_http2_prior_knowledge(mut self, http2_prior_knowledge: bool) -> Self {
+        self.http2_prior_knowledge = http2_prior_knowledge;
+        self
+    }
+
+    /// Sets how long a streamed response may stall, both before the response arrives and between two events.
+    ///
+    /// When exceeded, the stream yields [`Error::Timeout`](crate::err::Error::Timeout) and ends. There is no timeout by default
+    pub fn with_stream_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.stream_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long a streamed response may take to deliver its first chunk, counted from when the request is sent.
+    ///
+    /// When exceeded, the stream yields [`Error::Timeout`](crate::err::Error::Timeout) and ends, e.g. to give up on a speculative request.
+    /// Once the first chunk arrived it no longer applies, unlike [`Self::with_stream_idle_timeout`]. There is no timeout by default
+    pub fn with_first_token_timeout(mut self, timeout: Duration) -> Self {
+        self.first_token_timeout = Some(timeout);
+        self
+    }
+
+    /// Retries opening streamed responses when it fails with a retryable error, see [`Error::is_retryable`](crate::err::Error::is_retryable),
+    /// until `deadline` has elapsed since the first attempt.
+    ///
+    /// Retries wait 500ms, then twice as long before every next one, and never start past the deadline. They only happen before
+    /// the first byte of the stream is received, so no part of the response is yielded twice. Streamed requests are not retried by default
+    pub fn with_stream_connect_deadline(mut self, deadline: Duration) -> Self {
+        self.stream_connect_deadline = Some(deadline);
+        self
+    }
+
+    /// Resumes streamed responses up to `max_resumptions` times when their connection drops mid-stream, which is disabled by default.
+    ///
+    /// The request is sent again with a `Last-Event-ID` header holding the ID of the last event received, and the events of the new
+    /// response whose IDs were already received are skipped, so no content is yielded twice. This only works with backends that
+    /// send event IDs and resume from that header, e.g. some gateways and relays: OpenAI and Azure OpenAI send no event IDs,
+    /// in which case the error of the disconnection is yielded as if this was disabled. Stalls, see [`Self::with_stream_idle_timeout`],
+    /// are not resumed
+    pub fn with_stream_resumption(mut self, max_resumptions: usize) -> Self {
+        self.max_stream_resumptions = max_resumptions;
+        self
+    }
+
+    /// Accepts gzip, deflate and brotli compressed responses, including streamed ones, which is enabled by default.
+    ///
+    /// Responses are decompressed transparently. It is ignored on `wasm32`, where the browser negotiates the compression itself
+    pub fn with_compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Paces the requests from the `x-ratelimit-*` headers of the latest response, which is disabled by default.
+    ///
+    /// Once less than a tenth of the request or token budget remains, requests are delayed to spread the rest of it until the limit resets,
+    /// instead of bursting into 429 errors. It is ignored on `wasm32`
+    pub fn with_adaptive_throttling(mut self, adaptive_throttling: bool) -> Self {
+        self.adaptive_throttling = adaptive_throttling;
+        self
+    }
+
+    /// Sends requests again up to `max_retries` times when they fail with a retryable error, see [`Error::is_retryable`](crate::err::Error::is_retryable).
+    ///
+    /// Retries are disabled by default. They wait 500ms, then twice as long before every next one. Streamed requests are not
+    /// retried, see [`Self::with_stream_connect_deadline`] instead. It is ignored on `wasm32`
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Rejects requests that set both `temperature` and `top_p` to non-default values, which is disabled by default.
+    ///
+    /// See [`CompletionOptions::validate_sampling`]. When disabled, such requests are sent anyway, with a warning if the `tracing` feature is enabled.
+    pub fn with_strict_sampling(mut self, strict_sampling: bool) -> Self {
+        self.strict_sampling = strict_sampling;
+        self
+    }
+
+    /// Checks the messages with [`validate_messages`](crate::types::validate_messages) before sending them, which is disabled by default.
+    ///
+    /// Malformed conversations then fail with a descriptive [`Error::InvalidRequest`](crate::err::Error::InvalidRequest)
+    /// instead of an opaque 400 response. Leave it disabled to send messages the validation doesn't know about.
+    pub fn with_message_validation(mut self, validate_messages: bool) -> Self {
+        self.validate_messages = validate_messages;
+        self
+    }
+
+    /// Checks the completion options with [`CompletionOptions::validate`] before sending them, which is enabled by default.
+    ///
+    /// Disable it to send combinations of options the validation rejects, e.g. with a backend that supports them.
+    pub fn with_request_validation(mut self, validate_requests: bool) -> Self {
+        self.validate_requests = validate_requests;
+        self
+    }
+
+    /// Sets the total timeout of a request, from sending it until the response body has been read. There is no timeout by default.
+    ///
+    /// It is ignored on `wasm32`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the organization sent in the `OpenAI-Organization` header when a request is sent with [`Organization::Default`],
+    /// e.g. an empty `org`.
+    ///
+    /// The header is omitted when both are empty, or when a request is sent with [`Organization::None`].
+    pub fn with_organization<S: Into<String>>(mut self, organization: S) -> Self {
+        self.organization = Some(organization.into());
+        self
+    }
+
+    /// Sets the project sent in the `OpenAI-Project` header, required by project-scoped keys.
+    ///
+    /// It is sent alongside the organization, and neither header is sent when unset.
+    pub fn with_project(mut self, project: Option<String>) -> Self {
+        self.project = project;
+        self
+    }
+
+    /// Sets the `User-Agent` header of the requests, which defaults to `chatgpt_rs/<version>`
+    pub fn with_user_agent<S: Into<String>>(mut self, user_agent: S) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Registers an interceptor called around every request, after the ones already registered
+    pub fn with_interceptor(mut self, interceptor: Arc<dyn Interceptor>) -> Self {
+        self.interceptors.push(interceptor);
+        self
+    }
+
+    /// Sends the requests with a custom transport instead of the built-in `reqwest` client, e.g. a fake one in unit tests.
+    ///
+    /// The connection options, e.g. [`Self::with_timeout`] or [`Self::with_proxy`], only configure the built-in client,
+    /// so they are up to the transport. Not available on `wasm32`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_http_client(mut self, http_client: Arc<dyn HttpClient>) -> Self {
+        self.http_client = Some(Transport(http_client));
+        self
+    }
+
+    /// Sets the model used when [`CompletionOptions::model`] is not set, defaults to [`Model::Gpt4oMini`]
+    pub fn with_default_model<M: Into<Model>>(mut self, model: M) -> Self {
+        self.default_model = model.into();
+        self
+    }
+
+    /// Returns the url the chat completions are sent to
+    pub fn chat_completions_url(&self) -> crate::Result<Url> {
+        match (&self.api_url, &self.azure) {
+            (Some(api_url), _) => Ok(api_url.clone()),
+            (None, Some(azure)) => azure.chat_completions_url(),
+            (None, None) => self.endpoint("chat/completions"),
+        }
+    }
+
+    /// Returns the url the embeddings requests are sent to
+    pub fn embeddings_url(&self) -> crate::Result<Url> {
+        match &self.azure {
+            Some(azure) => azure.endpoint("embeddings"),
+            None => self.endpoint("embeddings"),
+        }
+    }
+
+    /// Returns the url the legacy text completions are sent to
+    pub fn completions_url(&self) -> crate::Result<Url> {
+        match &self.azure {
+            Some(azure) => azure.endpoint("completions"),
+            None => self.endpoint("completions"),
+        }
+    }
+
+    /// Resolves an endpoint path relative to the backend API url
+    pub(crate) fn endpoint(&self, path: &str) -> crate::Result<Url> {
+        let mut base = self.backend_api_url.clone();
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        base.join(path)
+            .map_err(|err| crate::err::Error::ParsingError(err.to_string()))
+    }
 }
 
 impl Default for ClientOptions {
     fn default() -> Self {
         Self {
-            backend_api_url: Url::from_str("https://api.openai.com/v1/chat/completions").unwrap(),
+            backend_api_url: Url::from_str("https://api.openai.com/v1/").unwrap(),
+            api_url: None,
+            azure: None,
+            pool_max_idle_per_host: usize::MAX,
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            http2_prior_knowledge: false,
+            proxy: None,
+            stream_idle_timeout: None,
+            first_token_timeout: None,
+            stream_connect_deadline: None,
+            max_stream_resumptions: 0,
+            compression: true,
+            adaptive_throttling: false,
+            max_retries: 0,
+            strict_sampling: false,
+            validate_messages: false,
+            validate_requests: true,
+            timeout: None,
+            organization: None,
+            project: None,
+            referer: None,
+            title: None,
+            user_agent: None,
+            interceptors: Interceptors::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            http_client: None,
+            default_model: Model::Gpt4oMini,
         }
     }
 }
@@ -39,8 +479,10 @@ impl Default for ClientOptions {
 #[derive(Debug, Clone)]
 pub struct ChatGPT {
     client: reqwest::Client,
-    options: ClientOptions,
+    pub(crate) options: ClientOptions,
     api_key: String,
+    headers: HeaderMap,
+    throttle: Arc<Throttle>,
 }
 
 impl ChatGPT {
@@ -51,98 +493,318 @@ impl ChatGPT {
 
     /// Constructs a new ChatGPT client with the specified client options
     pub fn with_options<S: Into<String>>(token: S, options: ClientOptions) -> crate::Result<Self> {
-        let token = token.into();
-        let client = reqwest::ClientBuilder::new().build()?;
+        let client = http_client(&options)?;
         Ok(Self {
             client,
             options,
-            api_key: token,
+            api_key: token.into(),
+            headers: HeaderMap::new(),
+            throttle: Arc::default(),
         })
     }
 
+    /// Constructs a new ChatGPT client with the API key of the `OPENAI_API_KEY` environment variable, see [`Self::from_env_var`]
+    pub fn from_env() -> crate::Result<Self> {
+        Self::from_env_var(DEFAULT_API_KEY_VAR)
+    }
+
+    /// Constructs a new ChatGPT client with the API key of the given environment variable.
+    ///
+    /// The organization and project are read from the `OPENAI_ORG_ID` and `OPENAI_PROJECT_ID` variables when set.
+    /// Fails with [`Error::MissingApiKey`](crate::err::Error::MissingApiKey) if the variable is not set or empty.
+    pub fn from_env_var(name: &str) -> crate::Result<Self> {
+        Self::with_options(
+            api_key_from_env(name)?,
+            options_from_env(ClientOptions::default()),
+        )
+    }
+
+    /// Creates a builder to configure a client in a single chain
+    ///
+    /// Example:
+    /// ```rust,no_run
+    /// # use chatgpt::client::ChatGPT;
+    /// # use std::time::Duration;
+    /// # fn main() -> chatgpt::Result<()> {
+    /// let client = ChatGPT::builder()
+    ///     .model("gpt-4o")
+    ///     .timeout(Duration::from_secs(60))
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn builder() -> ChatGPTBuilder {
+        ChatGPTBuilder::default()
+    }
+
+    /// Returns a copy of this client which sends the extra headers with every request, e.g. a trace id required by a gateway.
+    ///
+    /// The headers are merged with the default headers of the crate and take precedence over them, so they can also override e.g. `Authorization`.
+    /// The copy shares the connection pool of this client.
+    pub fn with_headers(&self, headers: HeaderMap) -> Self {
+        let mut client = self.clone();
+        client.headers.extend(headers);
+        client
+    }
+
+    /// Returns the rate limits returned with the latest response, shared by the copies of this client
+    pub fn rate_limits(&self) -> Option<RateLimits> {
+        self.throttle.limits()
+    }
+
+    /// Drops the client and yields to the runtime a few times, giving it a chance to close the idle connections before returning,
+    /// e.g. at the end of a short-lived CLI invocation.
+    ///
+    /// This is best-effort: it doesn't wait for the connections to be closed. The connection pool is shared by the copies of the
+    /// client, including the ones held by [`Conversation`]s and by the [`ChatStream`]s still alive, so the connections are only
+    /// closed once the last of them is dropped, possibly after this returns.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn shutdown(self) {
+        drop(self);
+        // The pool and each connection are driven by tasks of the runtime, which end once they are polled
+        // after the pool is dropped
+        for _ in 0..SHUTDOWN_YIELDS {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    /// Starts a new conversation, which keeps track of the message history
+    pub fn new_conversation(&self, org: impl Into<Organization>) -> Conversation {
+        Conversation::new(self.clone(), org)
+    }
+
     /// Sends a messages and gets ChatGPT response.
     ///
     /// Note that usually it takes the AI around ~10-30 seconds to respond because of how the backend API is implemented.
     /// Because of that, sometimes you might want to use [`Self::send_message_streaming()`]
     ///
     /// Example:
-    /// ```rust
-    /// # use chatgpt::types::Message;
+    /// ```rust,no_run
     /// # use chatgpt::client::ChatGPT;
     /// # #[tokio::main]
     /// # async fn main() -> chatgpt::Result<()> {
     /// # let mut client = ChatGPT::new(std::env::var("OPENAI_SK").unwrap())?;
-    /// let messages = vec![Message {
-    ///     role: "user".to_owned(),
-    ///     content: "Write me a simple sorting algorithm in Rust".to_owned(),
-    /// }];
-    /// let response: String = client.send_message(messages).await?;
+    /// let response: String = client
+    ///     .send_message(
+    ///         "Write me a simple sorting algorithm in Rust",
+    ///         Default::default(),
+    ///         String::new(),
+    ///     )
+    ///     .await?;
     /// println!("{response}");
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn send_message<S: Into<Vec<Message>>>(
+    pub async fn send_message<S: IntoMessages>(
         &self,
         message: S,
         options: CompletionOptions,
-        org: String,
+        org: impl Into<Organization>,
     ) -> crate::Result<String> {
-        self.send_message_full(message, options, org)
+        let response = self.send_message_full(message, options, org).await?;
+        let choice = response.choices.into_iter().next().ok_or_else(|| {
+            crate::err::Error::BackendError("the response contains no choices".to_owned())
+        })?;
+        Ok(choice
+            .message
+            .content
+            .map(Content::into_text)
+            .unwrap_or_default())
+    }
+
+    /// Like [`Self::send_message`], but uses `model` for this call only, whatever the model of the options.
+    ///
+    /// The options are borrowed, so they can be shared between calls routed to different models.
+    pub async fn send_message_with_model<M: Into<Model>, S: IntoMessages>(
+        &self,
+        model: M,
+        message: S,
+        options: &CompletionOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<String> {
+        self.send_message(message, options.clone().with_model(model), org)
             .await
-            .map(|value| {
-                value.choices[0]
-                    .message
-                    .content
-                    .to_owned()
-                    .unwrap_or(String::new())
-            })
     }
 
-    pub async fn send_message_streaming<S: Into<Vec<Message>>>(
+    /// Sends messages and gets the content of every choice of ChatGPT response, in order.
+    ///
+    /// Use it with [`CompletionOptions::n`] to generate several completions at once. Choices without content are skipped.
+    pub async fn send_message_all<S: IntoMessages>(
         &self,
         message: S,
         options: CompletionOptions,
-        org: String,
-    ) -> crate::Result<impl Stream<Item = crate::Result<ResponsePart>>> {
-        let message = message.into();
-        let mut body = serde_json::to_value(options)?;
+        org: impl Into<Organization>,
+    ) -> crate::Result<Vec<String>> {
+        let response = self.send_message_full(message, options, org).await?;
+        Ok(response
+            .choices
+            .into_iter()
+            .filter_map(|choice| choice.message.content)
+            .map(Content::into_text)
+            .collect())
+    }
 
-        body["stream"] = serde_json::Value::Bool(true);
-        if body.get("model") == Some(&serde_json::Value::Null) {
-            body["model"] = serde_json::Value::String(String::from("gpt-4"));
+    /// Sends messages forcing JSON output, and parses the content of the response into `T`.
+    ///
+    /// The response format defaults to [`ResponseFormat::JsonObject`], in which case the messages must also instruct the model to produce JSON.
+    /// Set [`CompletionOptions::response_format`] to a [`ResponseFormat::JsonSchema`] to enforce the shape of the output.
+    /// If the content doesn't match `T`, [`Error::ContentParseError`](crate::err::Error::ContentParseError) holds the raw content.
+    pub async fn send_message_parsed<T: DeserializeOwned, S: IntoMessages>(
+        &self,
+        message: S,
+        mut options: CompletionOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<T> {
+        options
+            .response_format
+            .get_or_insert(ResponseFormat::JsonObject);
+        let content = self.send_message(message, options, org).await?;
+        serde_json::from_str(&content)
+            .map_err(|source| crate::err::Error::ContentParseError { content, source })
+    }
+
+    /// Sends messages and gets ChatGPT response as a [`ChatStream`] of parts, ending with [`ResponsePart::Done`]
+    ///
+    /// If a stream idle timeout is configured with [`ClientOptions::with_stream_idle_timeout`], the stream yields
+    /// [`Error::Timeout`](crate::err::Error::Timeout) and ends when the response or the next event takes longer than it to arrive.
+    /// The same goes for the first chunk with [`ClientOptions::with_first_token_timeout`].
+    ///
+    /// Not available on `wasm32`, where the stream and its timeouts rely on the native HTTP backend and the tokio timer.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "chat_completion_stream",
+            skip_all,
+            fields(model = tracing::field::Empty, messages = tracing::field::Empty)
+        )
+    )]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn send_message_streaming<S: IntoMessages>(
+        &self,
+        message: S,
+        options: CompletionOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<ChatStream> {
+        let options = CompletionOptions {
+            stream: Some(true),
+            ..options
+        };
+        let body = completion_body(self.messages(message)?, options, &self.options)?;
+        #[cfg(feature = "tracing")]
+        let (span, started) = (record_request(&body), std::time::Instant::now());
+        let (idle_timeout, first_token_timeout) = (
+            self.options.stream_idle_timeout,
+            self.options.first_token_timeout,
+        );
+        let request = self.post_chat_completions(org.into())?.json(&body);
+        let resumption = request
+            .try_clone()
+            .filter(|_| self.options.max_stream_resumptions > 0);
+        let (resp, sent) = self.connect_stream(request).await?;
+        let status = resp.status();
+        if !status.is_success() {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(%status, "chat completion stream failed");
+            return Err(crate::err::Error::api_error(status, resp.text().await?));
         }
-        body["messages"] = serde_json::to_value(message)?;
-        let stream = self
-            .client
-            .request(Method::POST, self.options.backend_api_url.clone())
-            .header("Content-Type", "application/json".to_owned())
-            .header("Authorization", format!("Bearer {}", self.api_key.clone()))
-            .header("OpenAI-Organization", org)
-            .header("api-key", self.api_key.clone())
-            .header("HTTP-Referer", "https://spoke.app")
-            .header("X-Title", "spoke")
-            .json(&body)
-            .send()
-            .await?
-            .bytes_stream()
-            .eventsource();
-        Ok(stream.map(move |part| {
-            let chunk = part?.data;
-            if chunk == "[DONE]" {
-                crate::Result::Ok(ResponsePart::Done)
-            } else {
-                let data: ChatCompletionChunk = serde_json::from_str(&chunk)?;
-                crate::Result::Ok(ResponsePart::Chunk(data))
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            %status,
+            latency_ms = started.elapsed().as_millis() as u64,
+            "chat completion stream started"
+        );
+        let stream = parse_events(resumable_events(
+            self.clone(),
+            resp,
+            resumption,
+            self.options.max_stream_resumptions,
+        ));
+        let stream = with_idle_timeout(stream, idle_timeout);
+        Ok(ChatStream::new(
+            with_first_token_timeout(stream, first_token_timeout, sent).map(move |part| {
+                #[cfg(feature = "tracing")]
+                if let Ok(ResponsePart::Done) = part {
+                    let _enter = span.enter();
+                    tracing::debug!(
+                        latency_ms = started.elapsed().as_millis() as u64,
+                        "chat completion stream finished"
+                    );
+                }
+                part
+            }),
+        ))
+    }
+    /// Sends messages, streams the response and collects it into the response [`Self::send_message_full`] would have returned.
+    ///
+    /// The usage is requested from the API with [`StreamOptions::include_usage`], unless it is explicitly disabled.
+    /// When the API doesn't return it, e.g. with some OpenAI-compatible backends, and the `tokenizer` feature is enabled,
+    /// it is estimated locally, which [`Usage::estimated`](crate::types::Usage::estimated) tells. Otherwise it is left zeroed.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn send_message_collected<S: IntoMessages>(
+        &self,
+        message: S,
+        mut options: CompletionOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<ConversationResponse> {
+        let messages = message.into_messages();
+        options
+            .stream_options
+            .get_or_insert_with(StreamOptions::default)
+            .include_usage
+            .get_or_insert(true);
+        #[cfg(feature = "tokenizer")]
+        let model = options
+            .model
+            .clone()
+            .unwrap_or_else(|| self.options.default_model.clone());
+        #[cfg(feature = "tokenizer")]
+        let prompt = messages.clone();
+        let stream = self.send_message_streaming(messages, options, org).await?;
+        #[allow(unused_mut)]
+        let mut response = crate::stream::collect_response(stream).await?;
+        #[cfg(feature = "tokenizer")]
+        if response.usage.total_tokens == 0 {
+            let completions: Vec<Message> = response
+                .choices
+                .iter()
+                .map(|choice| choice.message.clone())
+                .collect();
+            response.usage = crate::tokenizer::estimate_usage(&model, &prompt, &completions);
+        }
+        Ok(response)
+    }
+
+    /// Sends messages and forwards every part of the streamed response into the channel.
+    ///
+    /// Returns once [`ResponsePart::Done`] has been forwarded or the receiver has been dropped.
+    /// If the request or the stream fails, the error is returned and the sender is dropped, closing the channel.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn stream_to_channel<S: IntoMessages>(
+        &self,
+        message: S,
+        options: CompletionOptions,
+        org: impl Into<Organization>,
+        tx: mpsc::Sender<ResponsePart>,
+    ) -> crate::Result<()> {
+        let stream = self.send_message_streaming(message, options, org).await?;
+        futures_util::pin_mut!(stream);
+        while let Some(part) = stream.next().await {
+            let part = part?;
+            let done = part == ResponsePart::Done;
+            if tx.send(part).await.is_err() || done {
+                break;
             }
-        }))
+        }
+        Ok(())
     }
+
     /// Sends a message with parent message id and conversation id for conversations.
     ///
     /// Note that usually it takes the AI around ~10-30 seconds to respond because of how the backend API is implemented.
     /// Because of that, sometimes you might want to use [`Self::send_message_streaming()`]
     ///
     /// Example:
-    /// ```rust
+    /// ```rust,no_run
     /// # use chatgpt::types::Message;
     /// # use chatgpt::prelude::*;
     /// # use chatgpt::client::ChatGPT;
@@ -151,42 +813,985 @@ impl ChatGPT {
     /// # let mut client = ChatGPT::new(std::env::var("OPENAI_SK").unwrap())?;
     /// # let messages = vec![Message {
     /// #    role: "user".to_owned(),
-    /// #    content: "Write me a simple sorting algorithm in Rust".to_owned(),
+    /// #    content: Some("Write me a simple sorting algorithm in Rust".into()),
+    /// #    ..Default::default()
     /// # }];
-    /// # let response: ConversationResponse = client.send_message_full(messages).await?;
+    /// # let response: ConversationResponse = client
+    /// #     .send_message_full(messages, Default::default(), String::new())
+    /// #     .await?;
     /// # println!("{response:?}");
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn send_message_full<S: Into<Vec<Message>>>(
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "chat_completion",
+            skip_all,
+            fields(model = tracing::field::Empty, messages = tracing::field::Empty)
+        )
+    )]
+    pub async fn send_message_full<S: IntoMessages>(
         &self,
         message: S,
         options: CompletionOptions,
-        org: String,
+        org: impl Into<Organization>,
     ) -> crate::Result<ConversationResponse> {
-        let message = message.into();
-        let mut body = serde_json::to_value(options)?;
+        let body = completion_body(self.messages(message)?, options, &self.options)?;
+        #[cfg(feature = "tracing")]
+        let (_, started) = (record_request(&body), std::time::Instant::now());
+        let resp = self
+            .send(self.post_chat_completions(org.into())?.json(&body))
+            .await?;
+        let res = parse_response::<ConversationResponse>(resp).await;
+        #[cfg(feature = "tracing")]
+        match &res {
+            Ok(res) => tracing::debug!(
+                latency_ms = started.elapsed().as_millis() as u64,
+                prompt_tokens = res.usage.prompt_tokens,
+                completion_tokens = res.usage.completion_tokens,
+                total_tokens = res.usage.total_tokens,
+                "chat completion finished"
+            ),
+            Err(err) => tracing::warn!(error = %err, "chat completion failed"),
+        }
+        res
+    }
+
+    /// Sends messages and returns the raw body of the response, without parsing it.
+    ///
+    /// Use it to inspect fields that [`ConversationResponse`] doesn't model yet. Error statuses still fail with
+    /// [`Error::ApiError`](crate::err::Error::ApiError), which holds the raw body as well.
+    pub async fn send_message_raw<S: IntoMessages>(
+        &self,
+        message: S,
+        options: CompletionOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<String> {
+        let body = completion_body(self.messages(message)?, options, &self.options)?;
+        let resp = self
+            .send(self.post_chat_completions(org.into())?.json(&body))
+            .await?;
+        let status = resp.status();
+        let body = resp.text().await?;
+        if !status.is_success() {
+            return Err(crate::err::Error::api_error(status, body));
+        }
+        Ok(body)
+    }
+
+    /// Like [`Self::send_message`], but takes the messages from any iterator, e.g. a filtered history, without collecting them first
+    pub async fn send_messages<I, M>(
+        &self,
+        messages: I,
+        options: CompletionOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<String>
+    where
+        I: IntoIterator<Item = M>,
+        M: Into<Message>,
+    {
+        self.send_message(collect_messages(messages), options, org)
+            .await
+    }
+
+    /// Like [`Self::send_message_full`], but takes the messages from any iterator
+    pub async fn send_messages_full<I, M>(
+        &self,
+        messages: I,
+        options: CompletionOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<ConversationResponse>
+    where
+        I: IntoIterator<Item = M>,
+        M: Into<Message>,
+    {
+        self.send_message_full(collect_messages(messages), options, org)
+            .await
+    }
 
-        if body.get("model") == Some(&serde_json::Value::Null) {
-            body["model"] = serde_json::Value::String(String::from("gpt-3.5-turbo"));
+    /// Like [`Self::send_message_streaming`], but takes the messages from any iterator
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn send_messages_streaming<I, M>(
+        &self,
+        messages: I,
+        options: CompletionOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<ChatStream>
+    where
+        I: IntoIterator<Item = M>,
+        M: Into<Message>,
+    {
+        self.send_message_streaming(collect_messages(messages), options, org)
+            .await
+    }
+
+    /// Sends many requests concurrently, with at most `concurrency` of them in flight at once.
+    ///
+    /// Responses are yielded as soon as they arrive, so possibly out of order, each with the index of its request.
+    /// A failed request doesn't stop the others.
+    pub fn send_batch(
+        &self,
+        requests: Vec<(Vec<Message>, CompletionOptions)>,
+        concurrency: usize,
+        org: impl Into<Organization>,
+    ) -> impl Stream<Item = (usize, crate::Result<ConversationResponse>)> + '_ {
+        let org = org.into();
+        stream::iter(requests.into_iter().enumerate())
+            .map(move |(index, (messages, options))| {
+                let org = org.clone();
+                async move { (index, self.send_message_full(messages, options, org).await) }
+            })
+            .buffer_unordered(concurrency.max(1))
+    }
+
+    /// Sends a prompt to the legacy text completions endpoint and gets the completed text.
+    ///
+    /// Some older and fine-tuned models only work with this endpoint. The model defaults to `gpt-3.5-turbo-instruct`.
+    pub async fn complete<S: Into<String>>(
+        &self,
+        prompt: S,
+        mut options: CompletionOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<String> {
+        options
+            .model
+            .get_or_insert_with(|| Model::from("gpt-3.5-turbo-instruct"));
+        if self.options.validate_requests {
+            options.validate()?;
         }
-        body["messages"] = serde_json::to_value(message)?;
+        let mut body = serde_json::to_value(options)?;
+        body["prompt"] = serde_json::Value::String(prompt.into());
         let resp = self
-            .client
-            .request(Method::POST, self.options.backend_api_url.clone())
-            .header("Content-Type", "application/json".to_owned())
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("OpenAI-Organization", org)
-            .header("api-key", self.api_key.clone())
-            .header("HTTP-Referer", "https://spoke.app")
-            .header("X-Title", "spoke")
-            .json(&body)
-            .send()
+            .send(
+                self.post(self.options.completions_url()?, org.into())
+                    .json(&body),
+            )
             .await?;
-        let resp = resp.text().await?;
-        dbg!(&resp);
-        let res: ConversationResponse = serde_json::from_str(&resp)
-            .map_err(|e| crate::err::Error::ApiError(resp, format!("{}", e)))?;
-        Ok(res)
+        let response: TextCompletionResponse = parse_response(resp).await?;
+        Ok(response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.text)
+            .unwrap_or_default())
+    }
+
+    /// Creates embeddings of the inputs, one per input in the same order.
+    ///
+    /// The model defaults to `text-embedding-3-small`. Embeddings requested in [`EncodingFormat::Base64`](crate::types::EncodingFormat::Base64)
+    /// are more compact over the wire and decoded transparently.
+    pub async fn embeddings<I, S>(
+        &self,
+        input: I,
+        mut options: EmbeddingOptions,
+        org: impl Into<Organization>,
+    ) -> crate::Result<EmbeddingResponse>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        options
+            .model
+            .get_or_insert_with(|| Model::from("text-embedding-3-small"));
+        let mut body = serde_json::to_value(options)?;
+        body["input"] =
+            serde_json::to_value(input.into_iter().map(Into::into).collect::<Vec<String>>())?;
+        let resp = self
+            .send(
+                self.post(self.options.embeddings_url()?, org.into())
+                    .json(&body),
+            )
+            .await?;
+        parse_response(resp).await
+    }
+
+    /// Converts the messages to send, validating them if enabled
+    fn messages<S: IntoMessages>(&self, message: S) -> crate::Result<Vec<Message>> {
+        let messages = message.into_messages();
+        if self.options.validate_messages {
+            validate_messages(&messages)?;
+        }
+        Ok(messages)
+    }
+
+    /// Sends a request once the throttle allows it, recording the rate limits of the response
+    pub(crate) async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> crate::Result<reqwest::Response> {
+        self.send_rebuilding(request, reqwest::RequestBuilder::try_clone)
+            .await
+    }
+
+    /// Like [`Self::send`], but gets the request of every retry from `rebuild`, e.g. to build a multipart body again, as it can't be cloned.
+    ///
+    /// The request isn't retried when `rebuild` returns `None`.
+    pub(crate) async fn send_rebuilding<F>(
+        &self,
+        mut request: reqwest::RequestBuilder,
+        mut rebuild: F,
+    ) -> crate::Result<reqwest::Response>
+    where
+        F: FnMut(&reqwest::RequestBuilder) -> Option<reqwest::RequestBuilder>,
+    {
+        let mut attempt = 0;
+        loop {
+            let retry = match attempt < self.options.max_retries {
+                true => rebuild(&request),
+                false => None,
+            };
+            self.wait_for_throttle().await;
+            let result = self.execute(request).await;
+            if let Ok(resp) = &result {
+                self.record_rate_limits(resp);
+            }
+            let retryable = match &result {
+                Ok(resp) => crate::err::is_retryable_status(resp.status()),
+                Err(err) => err.is_retryable(),
+            };
+            match retry {
+                Some(next) if retryable && cfg!(not(target_arch = "wasm32")) => {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    tokio::time::sleep(RETRY_BASE_DELAY * 2u32.saturating_pow(attempt as u32))
+                        .await;
+                    request = next;
+                    attempt += 1;
+                }
+                _ => return result,
+            }
+        }
+    }
+
+    /// Opens a streamed response, retrying it with a backoff until the stream connect deadline, and returns it with when its request was sent.
+    ///
+    /// The body is not read before returning, which is what makes the retries safe.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn connect_stream(
+        &self,
+        mut request: reqwest::RequestBuilder,
+    ) -> crate::Result<(reqwest::Response, tokio::time::Instant)> {
+        let deadline = self
+            .options
+            .stream_connect_deadline
+            .map(|deadline| tokio::time::Instant::now() + deadline);
+        let mut attempt = 0;
+        loop {
+            let retry = deadline.and_then(|_| request.try_clone());
+            self.wait_for_throttle().await;
+            let sent = tokio::time::Instant::now();
+            let result = within(
+                self.options.first_token_timeout,
+                within(
+                    self.options.stream_idle_timeout,
+                    self.execute(request),
+                    "waiting for the response",
+                ),
+                "waiting for the first token",
+            )
+            .await
+            .and_then(|result| result)
+            .and_then(|result| result);
+            if let Ok(resp) = &result {
+                self.record_rate_limits(resp);
+            }
+            let retryable = match &result {
+                Ok(resp) => crate::err::is_retryable_status(resp.status()),
+                Err(err) => err.is_retryable(),
+            };
+            let delay = RETRY_BASE_DELAY * 2u32.saturating_pow(attempt);
+            match (retry, deadline) {
+                (Some(next), Some(deadline))
+                    if retryable && tokio::time::Instant::now() + delay < deadline =>
+                {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(attempt, "retrying the connection of the stream");
+                    tokio::time::sleep(delay).await;
+                    request = next;
+                    attempt += 1;
+                }
+                _ => return result.map(|resp| (resp, sent)),
+            }
+        }
+    }
+
+    /// Sends a request through the interceptors and the transport
+    async fn execute(&self, request: reqwest::RequestBuilder) -> crate::Result<reqwest::Response> {
+        let request = self
+            .options
+            .interceptors
+            .on_request(request.build()?)
+            .await?;
+        #[cfg(not(target_arch = "wasm32"))]
+        let resp = match &self.options.http_client {
+            Some(transport) => transport.execute(request).await?,
+            None => self.client.execute(request).await?,
+        };
+        #[cfg(target_arch = "wasm32")]
+        let resp = self.client.execute(request).await?;
+        self.options.interceptors.on_response(&resp).await;
+        Ok(resp)
+    }
+
+    /// Waits for the next slot of the throttle, if adaptive throttling is enabled
+    async fn wait_for_throttle(&self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.options.adaptive_throttling {
+            let delay = self.throttle.reserve();
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+
+    /// Records the rate limits of a response
+    fn record_rate_limits(&self, resp: &reqwest::Response) {
+        if let Some(limits) = RateLimits::from_headers(resp.headers()) {
+            self.throttle.record(limits);
+        }
+    }
+
+    /// Builds a POST request to the chat completions endpoint, with the authentication headers of the configured backend
+    fn post_chat_completions(&self, org: Organization) -> crate::Result<reqwest::RequestBuilder> {
+        Ok(self.post(self.options.chat_completions_url()?, org))
+    }
+
+    /// Builds a POST request with a JSON body, with the authentication headers of the configured backend
+    fn post(&self, url: Url, org: Organization) -> reqwest::RequestBuilder {
+        self.request(Method::POST, url, org)
+            .header("Content-Type", "application/json".to_owned())
+    }
+
+    /// Builds a request, with the authentication headers of the configured backend
+    pub(crate) fn request(
+        &self,
+        method: Method,
+        url: Url,
+        org: Organization,
+    ) -> reqwest::RequestBuilder {
+        let org = match org {
+            Organization::Default => self.options.organization.clone(),
+            Organization::None => None,
+            Organization::Id(org) => Some(org),
+        };
+        let mut request = self.client.request(method, url);
+        // Local servers, e.g. Ollama, don't need a key, in which case no authentication header is sent
+        if !self.api_key.is_empty() {
+            if self.options.azure.is_none() {
+                request = request.header("Authorization", format!("Bearer {}", self.api_key));
+            }
+            request = request.header("api-key", self.api_key.clone());
+        }
+        if self.options.azure.is_none() {
+            if let Some(referer) = &self.options.referer {
+                request = request.header("HTTP-Referer", referer.clone());
+            }
+            if let Some(title) = &self.options.title {
+                request = request.header("X-Title", title.clone());
+            }
+            if let Some(org) = org.filter(|org| !org.is_empty()) {
+                request = request.header("OpenAI-Organization", org);
+            }
+            if let Some(project) = &self.options.project {
+                request = request.header("OpenAI-Project", project.clone());
+            }
+        }
+        let user_agent = match &self.options.user_agent {
+            Some(user_agent) => user_agent.clone(),
+            None => DEFAULT_USER_AGENT.to_owned(),
+        };
+        request
+            .header(USER_AGENT, user_agent)
+            .headers(self.headers.clone())
+    }
+}
+
+/// A builder for [`ChatGPT`], created with [`ChatGPT::builder`]
+#[derive(Debug, Clone, Default)]
+pub struct ChatGPTBuilder {
+    api_key: Option<String>,
+    api_key_env: Option<String>,
+    options: ClientOptions,
+}
+
+impl ChatGPTBuilder {
+    /// Sets the API key. When not set, it is read from the `OPENAI_API_KEY` environment variable, see [`ChatGPT::from_env`]
+    pub fn api_key<S: Into<String>>(mut self, api_key: S) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Sets the environment variable the API key is read from when it is not set, instead of `OPENAI_API_KEY`, see [`ChatGPT::from_env_var`]
+    pub fn api_key_env<S: Into<String>>(mut self, name: S) -> Self {
+        self.api_key_env = Some(name.into());
+        self
+    }
+
+    /// Sets the model used when [`CompletionOptions::model`] is not set, see [`ClientOptions::with_default_model`]
+    pub fn model<M: Into<Model>>(mut self, model: M) -> Self {
+        self.options = self.options.with_default_model(model);
+        self
+    }
+
+    /// Sets the organization sent with requests, see [`ClientOptions::with_organization`]
+    pub fn organization<S: Into<String>>(mut self, organization: S) -> Self {
+        self.options = self.options.with_organization(organization);
+        self
+    }
+
+    /// Sets the total timeout of a request, see [`ClientOptions::with_timeout`]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options = self.options.with_timeout(timeout);
+        self
+    }
+
+    /// Sets the other client options. Call it before the other setters, as it replaces the options they set
+    pub fn options(mut self, options: ClientOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Builds the client, failing with [`crate::err::Error::MissingApiKey`] if no API key was set nor found in the environment
+    pub fn build(self) -> crate::Result<ChatGPT> {
+        let api_key = match self.api_key {
+            Some(api_key) => api_key,
+            None => api_key_from_env(self.api_key_env.as_deref().unwrap_or(DEFAULT_API_KEY_VAR))?,
+        };
+        ChatGPT::with_options(api_key, options_from_env(self.options))
+    }
+}
+
+/// Reads a non-empty API key from an environment variable
+fn api_key_from_env(name: &str) -> crate::Result<String> {
+    std::env::var(name)
+        .ok()
+        .filter(|api_key| !api_key.is_empty())
+        .ok_or_else(|| crate::err::Error::MissingApiKey(name.to_owned()))
+}
+
+/// Fills the organization and project left unset from the `OPENAI_ORG_ID` and `OPENAI_PROJECT_ID` environment variables
+fn options_from_env(mut options: ClientOptions) -> ClientOptions {
+    let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+    if options.organization.is_none() {
+        options.organization = var("OPENAI_ORG_ID");
+    }
+    if options.project.is_none() {
+        options.project = var("OPENAI_PROJECT_ID");
+    }
+    options
+}
+
+/// Collects messages from an iterator, converting them into [`Message`]s
+fn collect_messages<I, M>(messages: I) -> Vec<Message>
+where
+    I: IntoIterator<Item = M>,
+    M: Into<Message>,
+{
+    messages.into_iter().map(Into::into).collect()
+}
+
+/// Reads the JSON body of a response, surfacing error statuses and unparsable bodies as [`crate::err::Error::ApiError`]
+pub(crate) async fn parse_response<T: DeserializeOwned>(
+    resp: reqwest::Response,
+) -> crate::Result<T> {
+    let status = resp.status();
+    let body = resp.text().await?;
+    if !status.is_success() {
+        return Err(crate::err::Error::api_error(status, body));
+    }
+    let parsed = serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_str(&body));
+    parsed.map_err(|error| crate::err::Error::parse_error(status, body, error))
+}
+
+/// Builds the body of a chat completion request, falling back to the default model when none is set
+pub(crate) fn completion_body(
+    messages: Vec<Message>,
+    mut options: CompletionOptions,
+    client_options: &ClientOptions,
+) -> crate::Result<serde_json::Value> {
+    if let Err(err) = options.validate_sampling() {
+        if client_options.strict_sampling {
+            return Err(err);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::warn!(error = %err, "questionable sampling options");
+    }
+    let model = options
+        .model
+        .get_or_insert_with(|| client_options.default_model.clone());
+    if model.is_reasoning() && options.max_completion_tokens.is_none() {
+        options.max_completion_tokens = options.max_tokens.take();
+    }
+    if client_options.validate_requests {
+        if options.best_of.is_some() {
+            return Err(crate::err::Error::InvalidRequest(
+                "best_of is only supported by the legacy completions endpoint".to_owned(),
+            ));
+        }
+        options.validate()?;
+    }
+    let mut body = serde_json::to_value(options)?;
+    body["messages"] = serde_json::to_value(messages)?;
+    Ok(body)
+}
+
+/// Builds the HTTP client, with the connection options of the client options
+#[cfg(not(target_arch = "wasm32"))]
+fn http_client(options: &ClientOptions) -> crate::Result<reqwest::Client> {
+    let mut builder = reqwest::ClientBuilder::new()
+        .pool_max_idle_per_host(options.pool_max_idle_per_host)
+        .pool_idle_timeout(options.pool_idle_timeout)
+        .http2_adaptive_window(true)
+        .gzip(options.compression)
+        .deflate(options.compression)
+        .brotli(options.compression);
+    if options.http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+    if let Some(timeout) = options.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(proxy) = &options.proxy {
+        let mut reqwest_proxy = reqwest::Proxy::all(proxy.url.clone())?;
+        if let Some((username, password)) = &proxy.basic_auth {
+            reqwest_proxy = reqwest_proxy.basic_auth(username, password);
+        }
+        builder = builder.proxy(reqwest_proxy);
+    }
+    Ok(builder.build()?)
+}
+
+/// Builds the HTTP client on top of the `fetch` API of the browser, which manages the connections and timeouts itself
+#[cfg(target_arch = "wasm32")]
+fn http_client(_options: &ClientOptions) -> crate::Result<reqwest::Client> {
+    Ok(reqwest::ClientBuilder::new().build()?)
+}
+
+/// Records the metadata of a request on the current span, leaving the content of the messages out
+#[cfg(feature = "tracing")]
+fn record_request(body: &serde_json::Value) -> tracing::Span {
+    let span = tracing::Span::current();
+    span.record("model", body["model"].as_str().unwrap_or_default());
+    span.record(
+        "messages",
+        body["messages"].as_array().map_or(0, Vec::len) as u64,
+    );
+    span
+}
+
+/// Awaits the future, failing with [`crate::err::Error::Timeout`] if it takes longer than the timeout
+#[cfg(not(target_arch = "wasm32"))]
+async fn within<F: Future>(
+    timeout: Option<Duration>,
+    future: F,
+    waiting_for: &str,
+) -> crate::Result<F::Output> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await.map_err(|_| {
+            crate::err::Error::Timeout(format!("{waiting_for} took longer than {timeout:?}"))
+        }),
+        None => Ok(future.await),
+    }
+}
+
+/// Ends the stream with [`crate::err::Error::Timeout`] if an item takes longer than the timeout to arrive
+#[cfg(not(target_arch = "wasm32"))]
+fn with_idle_timeout<T, S>(
+    stream: S,
+    timeout: Option<Duration>,
+) -> impl Stream<Item = crate::Result<T>>
+where
+    S: Stream<Item = crate::Result<T>>,
+{
+    Box::pin(stream::unfold(
+        Some(Box::pin(stream)),
+        move |stream| async move {
+            let mut stream = stream?;
+            match within(timeout, stream.next(), "waiting for the next event").await {
+                Ok(Some(item)) => Some((item, Some(stream))),
+                Ok(None) => None,
+                Err(err) => Some((Err(err), None)),
+            }
+        },
+    ))
+}
+
+/// Ends the stream with [`crate::err::Error::Timeout`] if its first chunk doesn't arrive within the timeout after the request was sent
+#[cfg(not(target_arch = "wasm32"))]
+fn with_first_token_timeout<S>(
+    stream: S,
+    timeout: Option<Duration>,
+    sent: tokio::time::Instant,
+) -> impl Stream<Item = crate::Result<ResponsePart>>
+where
+    S: Stream<Item = crate::Result<ResponsePart>>,
+{
+    Box::pin(stream::unfold(
+        (Some(Box::pin(stream)), timeout),
+        move |(stream, timeout)| async move {
+            let mut stream = stream?;
+            let next = match timeout {
+                Some(timeout) => {
+                    match tokio::time::timeout_at(sent + timeout, stream.next()).await {
+                        Ok(next) => next,
+                        Err(_) => {
+                            let err = crate::err::Error::Timeout(format!(
+                                "waiting for the first token took longer than {timeout:?}"
+                            ));
+                            return Some((Err(err), (None, None)));
+                        }
+                    }
+                }
+                None => stream.next().await,
+            };
+            let item = next?;
+            let timeout = timeout.filter(|_| !matches!(item, Ok(ResponsePart::Chunk(_))));
+            Some((item, (Some(stream), timeout)))
+        },
+    ))
+}
+
+/// The server-sent events of a streamed response
+#[cfg(not(target_arch = "wasm32"))]
+type EventStreamOf =
+    std::pin::Pin<Box<dyn Stream<Item = Result<Event, EventStreamError<reqwest::Error>>> + Send>>;
+
+/// Yields the server-sent events of a streamed response, resuming it with `Last-Event-ID` when its connection drops mid-stream.
+///
+/// Only transport errors after an event with an ID are resumed, up to `max_resumptions` times, by sending `resumption` again.
+/// The events of a resumed response whose IDs were received before the disconnection are skipped. Any other error, or the
+/// failure of the resumption, is yielded as is.
+#[cfg(not(target_arch = "wasm32"))]
+fn resumable_events(
+    client: ChatGPT,
+    resp: reqwest::Response,
+    resumption: Option<reqwest::RequestBuilder>,
+    max_resumptions: usize,
+) -> impl Stream<Item = crate::Result<Event>> {
+    struct State {
+        client: ChatGPT,
+        events: EventStreamOf,
+        resumption: Option<reqwest::RequestBuilder>,
+        resumptions_left: usize,
+        /// IDs of the events received, and of those received before the latest resumption
+        seen: HashSet<String>,
+        replayed: HashSet<String>,
+        last_event_id: String,
+    }
+
+    let events: EventStreamOf = Box::pin(resp.bytes_stream().eventsource());
+    let state = State {
+        client,
+        events,
+        resumption,
+        resumptions_left: max_resumptions,
+        seen: HashSet::new(),
+        replayed: HashSet::new(),
+        last_event_id: String::new(),
+    };
+    stream::unfold(Some(state), |state| async move {
+        let mut state = state?;
+        loop {
+            match state.events.next().await? {
+                Ok(event) => {
+                    // The ID of an event without one is the ID of the previous event, so only replays are checked
+                    if state.replayed.contains(&event.id) {
+                        continue;
+                    }
+                    if !event.id.is_empty() {
+                        state.seen.insert(event.id.clone());
+                        state.last_event_id.clone_from(&event.id);
+                    }
+                    return Some((Ok(event), Some(state)));
+                }
+                Err(EventStreamError::Transport(err))
+                    if !err.is_timeout()
+                        && !state.last_event_id.is_empty()
+                        && state.resumptions_left > 0 =>
+                {
+                    let resumed = match state.resumption.as_ref().and_then(|r| r.try_clone()) {
+                        Some(request) => {
+                            let request = request.header("Last-Event-ID", &state.last_event_id);
+                            state.client.connect_stream(request).await
+                        }
+                        None => return Some((Err(err.into()), None)),
+                    };
+                    match resumed {
+                        Ok((resp, _)) if resp.status().is_success() => {
+                            #[cfg(feature = "tracing")]
+                            tracing::debug!(
+                                last_event_id = %state.last_event_id,
+                                "resumed the stream"
+                            );
+                            state.events = Box::pin(resp.bytes_stream().eventsource());
+                            state.replayed.clone_from(&state.seen);
+                            state.resumptions_left -= 1;
+                        }
+                        _ => return Some((Err(err.into()), None)),
+                    }
+                }
+                Err(err) => return Some((Err(err.into()), None)),
+            }
+        }
+    })
+}
+
+/// Parses the server-sent events of a streamed chat completion.
+///
+/// The data of an event holding incomplete JSON, e.g. a chunk split by a proxy, is buffered and parsed again along with the data of the next events.
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_events<S>(events: S) -> impl Stream<Item = crate::Result<ResponsePart>>
+where
+    S: Stream<Item = crate::Result<Event>>,
+{
+    let mut pending = String::new();
+    events.filter_map(move |event| {
+        let part = match event {
+            Ok(event) => {
+                pending.push_str(&event.data);
+                if pending.trim().is_empty() {
+                    pending.clear();
+                    return future::ready(None);
+                }
+                match parse_stream_event(&pending) {
+                    Err(crate::err::Error::SerdeError(err)) if err.is_eof() => None,
+                    part => {
+                        pending.clear();
+                        Some(part)
+                    }
+                }
+            }
+            Err(err) => Some(Err(err)),
+        };
+        future::ready(part)
+    })
+}
+
+/// Parses the data of a server-sent event of a streamed chat completion, surfacing error events as [`ResponsePart::Error`]
+///
+/// Surrounding whitespace is ignored, and the `[DONE]` sentinel is matched case-insensitively, as some proxies alter them.
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_stream_event(data: &str) -> crate::Result<ResponsePart> {
+    let data = data.trim();
+    if data.eq_ignore_ascii_case("[DONE]") {
+        return Ok(ResponsePart::Done);
+    }
+    match serde_json::from_str::<ChatCompletionChunk>(data) {
+        Ok(chunk) => Ok(ResponsePart::Chunk(chunk)),
+        Err(err) => match serde_json::from_str::<ApiErrorEnvelope>(data) {
+            Ok(envelope) => Ok(ResponsePart::Error(envelope.error)),
+            Err(_) => Err(err.into()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        completion_body, parse_events, parse_stream_event, with_first_token_timeout,
+        with_idle_timeout, ChatGPT, ClientOptions,
+    };
+    use crate::err::Error;
+    use crate::types::{ChatCompletionChunk, CompletionOptions, Model, ResponsePart};
+    use eventsource_stream::Eventsource;
+    use futures_util::{stream, StreamExt};
+    use std::time::Duration;
+
+    /// Parses the events of an SSE body received in the given pieces
+    async fn parse_pieces(pieces: Vec<&[u8]>) -> Vec<crate::Result<ResponsePart>> {
+        let bytes = stream::iter(pieces.into_iter().map(Ok::<_, reqwest::Error>));
+        parse_events(bytes.eventsource().map(|event| event.map_err(Into::into)))
+            .collect()
+            .await
+    }
+
+    fn chunk(content: &str) -> String {
+        serde_json::json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion.chunk",
+            "created": 1677652288,
+            "model": "gpt-4",
+            "choices": [{ "index": 0, "delta": { "content": content } }],
+        })
+        .to_string()
+    }
+
+    fn content(parts: &[crate::Result<ResponsePart>]) -> String {
+        parts
+            .iter()
+            .filter_map(|part| match part {
+                Ok(ResponsePart::Chunk(chunk)) => chunk.choices[0].delta.content.clone(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_lenient_done() {
+        let body = format!(
+            "data: {}\n\ndata:\n\ndata:   \n\ndata:  [done] \n\n",
+            chunk("Hi")
+        );
+        let parts = parse_pieces(vec![body.as_bytes()]).await;
+        assert_eq!(parts.len(), 2);
+        assert_eq!(content(&parts), "Hi");
+        assert!(matches!(parts[1], Ok(ResponsePart::Done)));
+    }
+
+    #[test]
+    fn test_builder() {
+        let client = ChatGPT::builder()
+            .api_key("sk-test")
+            .model("gpt-4o")
+            .organization("org-test")
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap();
+        assert_eq!(client.api_key, "sk-test");
+        assert_eq!(client.options.default_model, Model::Gpt4o);
+        assert_eq!(client.options.organization.as_deref(), Some("org-test"));
+        assert_eq!(client.options.timeout, Some(Duration::from_secs(30)));
+
+        // A variable of its own, as the tests run in parallel in the same process
+        const VAR: &str = "CHATGPT_TEST_API_KEY_BUILDER";
+        assert!(matches!(
+            ChatGPT::builder().api_key_env(VAR).build(),
+            Err(Error::MissingApiKey(name)) if name == VAR
+        ));
+        std::env::set_var(VAR, "sk-env");
+        let client = ChatGPT::builder().api_key_env(VAR).build().unwrap();
+        assert_eq!(client.api_key, "sk-env");
+        assert_eq!(ChatGPT::from_env_var(VAR).unwrap().api_key, "sk-env");
+        let client = ChatGPT::builder()
+            .api_key("sk-test")
+            .api_key_env(VAR)
+            .build()
+            .unwrap();
+        assert_eq!(client.api_key, "sk-test");
+        std::env::remove_var(VAR);
+    }
+
+    #[test]
+    fn test_request_validation() {
+        let options = || CompletionOptions {
+            best_of: Some(2),
+            ..Default::default()
+        };
+        assert!(matches!(
+            completion_body(vec![], options(), &ClientOptions::default()),
+            Err(Error::InvalidRequest(_))
+        ));
+        let client_options = ClientOptions::default().with_request_validation(false);
+        let body = completion_body(vec![], options(), &client_options).unwrap();
+        assert_eq!(body["best_of"], 2);
+    }
+
+    #[test]
+    fn test_from_env_var() {
+        assert!(matches!(
+            ChatGPT::from_env_var("CHATGPT_RS_TEST_MISSING_KEY"),
+            Err(Error::MissingApiKey(name)) if name == "CHATGPT_RS_TEST_MISSING_KEY"
+        ));
+        std::env::set_var("CHATGPT_RS_TEST_KEY", "sk-custom");
+        assert_eq!(
+            ChatGPT::from_env_var("CHATGPT_RS_TEST_KEY")
+                .unwrap()
+                .api_key,
+            "sk-custom"
+        );
+        std::env::remove_var("CHATGPT_RS_TEST_KEY");
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let stalled = stream::iter([Ok(1)]).chain(stream::pending());
+        let items: Vec<crate::Result<i32>> =
+            with_idle_timeout(stalled, Some(Duration::from_millis(50)))
+                .collect()
+                .await;
+        assert_eq!(items.len(), 2);
+        assert!(matches!(items[0], Ok(1)));
+        assert!(matches!(items[1], Err(Error::Timeout(_))));
+
+        let items: Vec<crate::Result<i32>> = with_idle_timeout(stream::iter([Ok(1), Ok(2)]), None)
+            .collect()
+            .await;
+        assert_eq!(items.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_first_token_timeout() {
+        let first: ChatCompletionChunk = serde_json::from_str(&chunk("Hello")).unwrap();
+        let delayed = |delay: u64| {
+            let first = ResponsePart::Chunk(first.clone());
+            stream::once(async move {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                Ok(first)
+            })
+        };
+        let timeout = Some(Duration::from_millis(100));
+
+        let late = delayed(200).chain(stream::iter([Ok(ResponsePart::Done)]));
+        let parts: Vec<_> = with_first_token_timeout(late, timeout, tokio::time::Instant::now())
+            .collect()
+            .await;
+        assert_eq!(parts.len(), 1);
+        assert!(matches!(parts[0], Err(Error::Timeout(_))));
+
+        let slow_after_first = delayed(10).chain(delayed(200));
+        let parts: Vec<_> =
+            with_first_token_timeout(slow_after_first, timeout, tokio::time::Instant::now())
+                .collect()
+                .await;
+        assert_eq!(parts.len(), 2);
+        assert!(parts
+            .iter()
+            .all(|part| matches!(part, Ok(ResponsePart::Chunk(_)))));
+    }
+
+    #[tokio::test]
+    async fn test_split_chunks() {
+        let body = format!(
+            "data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            chunk("Hello"),
+            chunk(" there!")
+        );
+        let body = body.as_bytes();
+        for split in 1..body.len() {
+            let parts = parse_pieces(vec![&body[..split], &body[split..]]).await;
+            assert_eq!(parts.len(), 3, "split at byte {split}");
+            assert_eq!(content(&parts), "Hello there!");
+            assert!(matches!(parts[2], Ok(ResponsePart::Done)));
+        }
+
+        let data = chunk("Hello");
+        let (head, tail) = data.split_at(data.len() / 2);
+        let body = format!("data: {head}\n\ndata: {tail}\n\ndata: [DONE]\n\n");
+        let parts = parse_pieces(vec![body.as_bytes()]).await;
+        assert_eq!(parts.len(), 2);
+        assert_eq!(content(&parts), "Hello");
+    }
+
+    #[test]
+    fn test_stream_error_event() {
+        let data = r#"{"error":{"message":"The server had an error while processing your request.","type":"server_error"}}"#;
+        match parse_stream_event(data) {
+            Ok(ResponsePart::Error(error)) => {
+                assert_eq!(
+                    error.message,
+                    "The server had an error while processing your request."
+                );
+                assert_eq!(error.kind.as_deref(), Some("server_error"));
+            }
+            other => panic!("expected an API error, got {other:?}"),
+        }
+        assert!(matches!(
+            parse_stream_event("[DONE]"),
+            Ok(ResponsePart::Done)
+        ));
+        assert!(matches!(
+            parse_stream_event("{}"),
+            Err(Error::SerdeError(_))
+        ));
     }
 }
@@ -0,0 +1,522 @@
+#[cfg(not(target_arch = "wasm32"))]
+use futures_util::stream::{self, Stream, StreamExt};
+
+use crate::client::{ChatGPT, Organization};
+use crate::err::Error;
+use crate::functions::FunctionRegistry;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::stream::collect_response;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::types::ResponsePart;
+use crate::types::{
+    validate_messages, CompletionOptions, Content, ConversationChoice, ConversationResponse,
+    FinishReason, Message, Model, Role, Usage,
+};
+
+/// The default instructions given to the model to summarize the older messages of a conversation
+pub const DEFAULT_SUMMARY_PROMPT: &str =
+    "Summarize the following conversation between a user and an assistant. \
+Keep every fact, decision and open question needed to continue it, and leave out pleasantries.";
+
+/// The instructions sent to the model by [`Conversation::continue_response`] to continue a truncated response
+pub const CONTINUE_PROMPT: &str =
+    "Your previous answer was cut off. Continue it exactly where it stopped, without repeating anything or adding a preamble.";
+
+/// The start of the system message holding a summary written by [`Conversation::summarize_older_than`]
+const SUMMARY_PREFIX: &str = "Summary of the earlier conversation: ";
+
+/// A conversation with ChatGPT, which keeps track of the message history between requests
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    client: ChatGPT,
+    org: Organization,
+    /// The messages sent and received so far
+    pub history: Vec<Message>,
+    /// Options of the completions sent in this conversation
+    pub options: CompletionOptions,
+    /// The model used by [`Self::summarize_older_than`], which defaults to the model of the conversation
+    pub summary_model: Option<Model>,
+    /// The instructions given to the model by [`Self::summarize_older_than`], see [`DEFAULT_SUMMARY_PROMPT`]
+    pub summary_prompt: String,
+    /// The role the system and developer messages of the history are sent with.
+    ///
+    /// When `None`, which is the default, it is the [`Model::instruction_role`] of the model of each request, so that a system prompt
+    /// is sent as a developer message to reasoning models and as a system message to the others. The history itself is left as is.
+    pub instruction_role: Option<Role>,
+    usage: Usage,
+    /// Why the last response of the AI ended, if it was received in this conversation and the history wasn't edited since
+    finish_reason: Option<FinishReason>,
+}
+
+impl Conversation {
+    /// Constructs a new conversation with an empty history
+    pub fn new(client: ChatGPT, org: impl Into<Organization>) -> Self {
+        Self::new_with_history(client, vec![], org)
+    }
+
+    /// Constructs a new conversation, continuing from the provided history
+    pub fn new_with_history(
+        client: ChatGPT,
+        history: Vec<Message>,
+        org: impl Into<Organization>,
+    ) -> Self {
+        Self {
+            client,
+            org: org.into(),
+            history,
+            options: CompletionOptions::default(),
+            summary_model: None,
+            summary_prompt: DEFAULT_SUMMARY_PROMPT.to_owned(),
+            instruction_role: None,
+            usage: Usage::default(),
+            finish_reason: None,
+        }
+    }
+
+    /// Forks the conversation at its current point, e.g. to try another continuation while keeping this one intact.
+    ///
+    /// The branch has its own copy of the history and options, so changes to either conversation don't affect the other.
+    /// Both share the connection pool and rate limits of the client.
+    pub fn branch(&self) -> Conversation {
+        self.clone()
+    }
+
+    /// Returns the tokens used by all the requests of this conversation since it started or [`Self::reset_token_usage`] was called,
+    /// including failed tool-calling loops, regenerated responses and summaries.
+    ///
+    /// Its cost can be estimated with [`Usage::estimated_cost`]. Streamed responses only count if the API returns their
+    /// usage, i.e. with `stream_options.include_usage`, or if the `tokenizer` feature estimates it.
+    pub fn token_usage(&self) -> &Usage {
+        &self.usage
+    }
+
+    /// Resets the running total of [`Self::token_usage`], e.g. at the start of a billing period
+    pub fn reset_token_usage(&mut self) {
+        self.usage = Usage::default();
+    }
+
+    /// Sets the system prompt of the conversation, e.g. to switch personas, leaving the rest of the history untouched.
+    ///
+    /// The prompt is the first message of the history, and replaces every system and developer message of the history,
+    /// so there is only ever one. It keeps the role of the first of them if it is a [`Role::Developer`] message.
+    /// The summaries inserted by [`Self::summarize_older_than`] are kept, after the prompt.
+    pub fn set_system<S: Into<String>>(&mut self, prompt: S) {
+        let mut role = Role::System;
+        let mut replaced = false;
+        self.history.retain(|message| match message.role() {
+            Some(prompt @ (Role::System | Role::Developer)) if !is_summary(message) => {
+                if !replaced {
+                    (role, replaced) = (prompt, true);
+                }
+                false
+            }
+            _ => true,
+        });
+        self.history.insert(0, Message::from((role, prompt.into())));
+    }
+
+    /// Returns the messages of the history from `index` on, which is empty if `index` is past its end
+    pub fn messages_since(&self, index: usize) -> &[Message] {
+        self.history.get(index..).unwrap_or_default()
+    }
+
+    /// Removes the message at `index` and all the following ones from the history, like [`Vec::truncate`].
+    ///
+    /// Fails with [`Error::InvalidRequest`], leaving the history untouched, if `index` is past the end of the history
+    /// or if it would separate tool calls from their results.
+    pub fn truncate_to(&mut self, index: usize) -> crate::Result<()> {
+        if index > self.history.len() {
+            return Err(Error::InvalidRequest(format!(
+                "can't truncate to message {index} a history of {} messages",
+                self.history.len()
+            )));
+        }
+        if has_pending_tool_calls(&self.history[..index]) {
+            return Err(Error::InvalidRequest(format!(
+                "truncating to message {index} would leave tool calls without their results"
+            )));
+        }
+        if index < self.history.len() {
+            self.finish_reason = None;
+        }
+        self.history.truncate(index);
+        Ok(())
+    }
+
+    /// Replaces the content of the message at `index`, keeping its role and tool calls, e.g. to fix a typo before [`Self::resend_from`].
+    ///
+    /// Only system, developer, user and assistant messages can be edited. Fails with [`Error::InvalidRequest`], leaving the history
+    /// untouched, if there is no message at `index` or if it is a tool or function result.
+    pub fn edit_message<S: Into<String>>(&mut self, index: usize, content: S) -> crate::Result<()> {
+        let message = self.message_at(index)?;
+        if !matches!(
+            message.role(),
+            Some(Role::System | Role::Developer | Role::User | Role::Assistant)
+        ) {
+            return Err(Error::InvalidRequest(format!(
+                "message {index} is a {} message, which can't be edited",
+                message.role
+            )));
+        }
+        if index + 1 == self.history.len() {
+            self.finish_reason = None;
+        }
+        let message = &mut self.history[index];
+        message.content = Some(Content::Text(content.into()));
+        Ok(())
+    }
+
+    /// Drops the messages after the user message at `index`, then sends the history up to it and returns the new response of the AI,
+    /// which is added to the history.
+    ///
+    /// Fails with [`Error::InvalidRequest`] if there is no user message at `index`.
+    /// The history is left untouched if the request fails.
+    pub async fn resend_from(&mut self, index: usize) -> crate::Result<String> {
+        let message = self.message_at(index)?;
+        if message.role() != Some(Role::User) {
+            return Err(Error::InvalidRequest(format!(
+                "message {index} is a {} message, only user messages can be resent",
+                message.role
+            )));
+        }
+        let mut messages = self.history[..=index].to_vec();
+        let reply = self.send(&messages, self.options.clone()).await?;
+        messages.push(reply.clone());
+        self.history = messages;
+        Ok(reply.content.map(Content::into_text).unwrap_or_default())
+    }
+
+    /// Returns the message at `index`, failing with [`Error::InvalidRequest`] if there is none
+    fn message_at(&self, index: usize) -> crate::Result<&Message> {
+        self.history.get(index).ok_or_else(|| {
+            Error::InvalidRequest(format!(
+                "there is no message {index} in a history of {} messages",
+                self.history.len()
+            ))
+        })
+    }
+
+    /// Checks that the history forms a conversation the API accepts, see [`validate_messages`]
+    pub fn validate(&self) -> crate::Result<()> {
+        validate_messages(&self.history)
+    }
+
+    /// Sends a user message and returns the response of the AI, adding both to the history
+    ///
+    /// The history is left untouched if the request fails.
+    pub async fn send_message<S: Into<String>>(&mut self, message: S) -> crate::Result<String> {
+        let mut messages = self.history.clone();
+        messages.push(Message::from(message.into()));
+        let reply = self.send(&messages, self.options.clone()).await?;
+        messages.push(reply.clone());
+        self.history = messages;
+        Ok(reply.content.map(Content::into_text).unwrap_or_default())
+    }
+
+    /// Sends a user message and streams the response of the AI, yielding the deltas of its content as they arrive.
+    ///
+    /// Both are added to the history once the response is complete, i.e. [`ResponsePart::Done`] was received.
+    /// The history is left untouched if the request or the stream fails, or if the stream is dropped before its end.
+    /// A stream ending before the response is complete yields an [`Error::BackendError`].
+    ///
+    /// Not available on `wasm32`, like [`ChatGPT::send_message_streaming`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn stream(
+        &mut self,
+        message: &str,
+    ) -> crate::Result<impl Stream<Item = crate::Result<String>> + '_> {
+        let message = Message::from(message);
+        let mut messages = self.history.clone();
+        messages.push(message.clone());
+        let messages = self.request_messages(&messages, &self.options);
+        let parts = self
+            .client
+            .send_messages_streaming(messages, self.options.clone(), self.org.clone())
+            .await?;
+        let state = Some((self, parts, message, Vec::new()));
+        Ok(stream::unfold(state, |state| async move {
+            let (conversation, mut parts, message, mut chunks) = state?;
+            loop {
+                let chunk = match parts.next().await {
+                    Some(Ok(ResponsePart::Chunk(chunk))) => chunk,
+                    Some(Ok(ResponsePart::Done)) => break,
+                    Some(Ok(ResponsePart::Error(error))) => {
+                        return Some((Err(Error::stream_error(error)), None))
+                    }
+                    Some(Err(error)) => return Some((Err(error), None)),
+                    None => {
+                        let error = Error::BackendError(
+                            "the stream ended before the response was complete".to_owned(),
+                        );
+                        return Some((Err(error), None));
+                    }
+                };
+                let delta: String = chunk
+                    .choices
+                    .iter()
+                    .filter(|choice| choice.index == 0)
+                    .filter_map(|choice| choice.delta.content.as_deref())
+                    .collect();
+                chunks.push(Ok(ResponsePart::Chunk(chunk)));
+                if !delta.is_empty() {
+                    return Some((Ok(delta), Some((conversation, parts, message, chunks))));
+                }
+            }
+            let response = match collect_response(stream::iter(chunks)).await {
+                Ok(response) => response,
+                Err(error) => return Some((Err(error), None)),
+            };
+            conversation.usage += &response.usage;
+            let Some(choice) = response.choices.into_iter().next() else {
+                let error = Error::BackendError("the response contains no choices".to_owned());
+                return Some((Err(error), None));
+            };
+            conversation.history.push(message);
+            conversation.history.push(choice.message);
+            conversation.finish_reason = choice.finish_reason;
+            None
+        }))
+    }
+
+    /// Replaces the last response of the AI with a new one, generated from the same history, and returns it.
+    ///
+    /// Fails with [`Error::InvalidRequest`] if the last message of the history is not from the AI.
+    /// The history is left untouched if the request fails.
+    pub async fn regenerate(&mut self) -> crate::Result<String> {
+        self.regenerate_with(self.options.clone()).await
+    }
+
+    /// Like [`Self::regenerate`], but with different options for this request, e.g. a higher temperature
+    pub async fn regenerate_with(&mut self, options: CompletionOptions) -> crate::Result<String> {
+        let Some((last, history)) = self.history.split_last() else {
+            return Err(Error::InvalidRequest(
+                "there is no response to regenerate in an empty conversation".to_owned(),
+            ));
+        };
+        if last.role() != Some(Role::Assistant) {
+            return Err(Error::InvalidRequest(format!(
+                "the last message is a {} message, not a response to regenerate",
+                last.role
+            )));
+        }
+        let history = history.to_vec();
+        let reply = self.send(&history, options).await?;
+        let content = reply.content.clone().map(Content::into_text);
+        *self.history.last_mut().unwrap() = reply;
+        Ok(content.unwrap_or_default())
+    }
+
+    /// Continues the last response of the AI, e.g. after it was truncated by `max_tokens`, and returns the whole response.
+    ///
+    /// The model is asked to continue with [`CONTINUE_PROMPT`], and its continuation is appended to the last message of the history,
+    /// so the instruction doesn't stay in the history. This is repeated while the continuation is itself truncated
+    /// with [`FinishReason::Length`], up to `max_continuations` requests.
+    /// Fails with [`Error::InvalidRequest`] if the last message of the history is not a response of the AI received in this
+    /// conversation and truncated with [`FinishReason::Length`]. The history is left untouched if a request fails.
+    pub async fn continue_response(&mut self, max_continuations: usize) -> crate::Result<String> {
+        let mut response = match self.history.last() {
+            Some(last) if last.role() == Some(Role::Assistant) => last.clone(),
+            _ => {
+                return Err(Error::InvalidRequest(
+                    "the last message is not a response to continue".to_owned(),
+                ))
+            }
+        };
+        if self.finish_reason != Some(FinishReason::Length) {
+            return Err(Error::InvalidRequest(
+                "the last response was not truncated by the token limit".to_owned(),
+            ));
+        }
+        let mut finish_reason = self.finish_reason;
+        let history = self.history[..self.history.len() - 1].to_vec();
+        for _ in 0..max_continuations {
+            let mut messages = history.to_vec();
+            messages.push(response.clone());
+            messages.push(Message::from(CONTINUE_PROMPT));
+            let choice = self.send_choice(&messages, self.options.clone()).await?;
+            if let Some(continuation) = &choice.message.content {
+                response
+                    .content
+                    .get_or_insert_with(Content::default)
+                    .push_str(&continuation.text());
+            }
+            finish_reason = choice.finish_reason;
+            if finish_reason != Some(FinishReason::Length) {
+                break;
+            }
+        }
+        let content = response.content.clone().map(Content::into_text);
+        *self.history.last_mut().unwrap() = response;
+        self.finish_reason = finish_reason;
+        Ok(content.unwrap_or_default())
+    }
+
+    /// Sends a user message, letting the AI call the functions of the registry until it returns a final answer.
+    ///
+    /// Every tool call is answered by invoking the matching handler and sending its result back to the AI,
+    /// up to [`FunctionRegistry::max_iterations`] requests. The message, the tool calls and their results are added to the history,
+    /// which is left untouched if any request or handler fails.
+    pub async fn send_with_tools(
+        &mut self,
+        message: &str,
+        registry: &FunctionRegistry,
+    ) -> crate::Result<String> {
+        let mut messages = self.history.clone();
+        messages.push(Message::from(message));
+        let mut options = self.options.clone();
+        options.tools = Some(registry.tools());
+
+        for _ in 0..registry.max_iterations() {
+            let reply = self.send(&messages, options.clone()).await?;
+            messages.push(reply.clone());
+            match reply.tool_calls {
+                Some(calls) if !calls.is_empty() => {
+                    for call in &calls {
+                        messages.push(registry.call(call)?);
+                    }
+                }
+                _ => {
+                    self.history = messages;
+                    return Ok(reply.content.map(Content::into_text).unwrap_or_default());
+                }
+            }
+        }
+        Err(Error::FunctionCallError(format!(
+            "no final answer after {} iterations",
+            registry.max_iterations()
+        )))
+    }
+
+    /// Replaces all but the last `n` messages of the history with a summary written by the model, and returns it.
+    ///
+    /// The system prompts at the start of the history are kept as they are, and the summary is inserted after them as a system message.
+    /// The last `n` messages are extended backwards if needed, so that tool results stay with the tool calls they answer.
+    /// Returns `None` without sending anything if there is nothing to summarize. The history is left untouched if the request fails.
+    pub async fn summarize_older_than(&mut self, n: usize) -> crate::Result<Option<String>> {
+        let start = self
+            .history
+            .iter()
+            .take_while(|message| matches!(message.role(), Some(Role::System | Role::Developer)))
+            .count();
+        let mut end = self.history.len().saturating_sub(n).max(start);
+        while end > start
+            && self
+                .history
+                .get(end)
+                .is_some_and(|message| message.role() == Some(Role::Tool))
+        {
+            end -= 1;
+        }
+        if end == start {
+            return Ok(None);
+        }
+
+        let transcript: Vec<String> = self.history[start..end]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let messages = [
+            Message::from((Role::System, self.summary_prompt.as_str())),
+            Message::from(transcript.join("\n")),
+        ];
+        let options = CompletionOptions {
+            model: self
+                .summary_model
+                .clone()
+                .or_else(|| self.options.model.clone()),
+            ..Default::default()
+        };
+        let summary = self
+            .send_choice(&messages, options)
+            .await?
+            .message
+            .content
+            .map(Content::into_text)
+            .unwrap_or_default();
+        self.history.splice(
+            start..end,
+            [Message::from((
+                Role::System,
+                format!("{SUMMARY_PREFIX}{summary}"),
+            ))],
+        );
+        Ok(Some(summary))
+    }
+
+    /// Sends the messages and returns the response to add to the history, recording why it ended
+    async fn send(
+        &mut self,
+        messages: &[Message],
+        options: CompletionOptions,
+    ) -> crate::Result<Message> {
+        let choice = self.send_choice(messages, options).await?;
+        self.finish_reason = choice.finish_reason;
+        Ok(choice.message)
+    }
+
+    /// Sends the messages and returns the first choice of the response, adding its usage to the running total
+    async fn send_choice(
+        &mut self,
+        messages: &[Message],
+        options: CompletionOptions,
+    ) -> crate::Result<ConversationChoice> {
+        let messages = self.request_messages(messages, &options);
+        let response: ConversationResponse = self
+            .client
+            .send_message_full(messages, options, self.org.clone())
+            .await?;
+        self.usage += &response.usage;
+        response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::BackendError("the response contains no choices".to_owned()))
+    }
+
+    /// Returns the messages to send with the options, with the system and developer messages sent with the [`Self::instruction_role`]
+    fn request_messages(&self, messages: &[Message], options: &CompletionOptions) -> Vec<Message> {
+        let role = self.instruction_role.unwrap_or_else(|| {
+            options
+                .model
+                .as_ref()
+                .unwrap_or(&self.client.options.default_model)
+                .instruction_role()
+        });
+        messages
+            .iter()
+            .map(|message| match message.role() {
+                Some(Role::System | Role::Developer) => Message {
+                    role: role.as_str().to_owned(),
+                    ..message.clone()
+                },
+                _ => message.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Whether the message is a summary inserted by [`Conversation::summarize_older_than`]
+fn is_summary(message: &Message) -> bool {
+    message.role() == Some(Role::System)
+        && matches!(&message.content, Some(Content::Text(text)) if text.starts_with(SUMMARY_PREFIX))
+}
+
+/// Whether the last assistant message with tool calls misses some of its results at the end of the messages
+fn has_pending_tool_calls(messages: &[Message]) -> bool {
+    let results = messages
+        .iter()
+        .rev()
+        .take_while(|message| message.role() == Some(Role::Tool))
+        .count();
+    let Some(call) = messages[..messages.len() - results].last() else {
+        return false;
+    };
+    let answered: Vec<_> = messages[messages.len() - results..]
+        .iter()
+        .filter_map(|message| message.tool_call_id.as_deref())
+        .collect();
+    call.tool_calls
+        .iter()
+        .flatten()
+        .any(|call| !answered.contains(&call.id.as_str()))
+}
@@ -2,14 +2,17 @@ use std::string::FromUtf8Error;
 
 use eventsource_stream::EventStreamError;
 use reqwest::header::InvalidHeaderValue;
+use reqwest::StatusCode;
 use thiserror::Error;
 
+use crate::types::{ApiErrorBody, ApiErrorEnvelope};
+
 /// An error enum, used in the Result
 #[derive(Debug, Error)]
 pub enum Error {
-    /// A reqwest-provoked error has occurred
+    /// A reqwest-provoked error has occurred. Timeouts are reported as [`Error::Timeout`] instead
     #[error("An error occurred when processing a request: {0}")]
-    ClientError(#[from] reqwest::Error),
+    ClientError(#[source] reqwest::Error),
     /// Invalid header configuration error. Probably because of the custom User-Agent header
     #[error("Invalid configuration provided: {0}")]
     InvalidConfiguration(#[from] InvalidHeaderValue),
@@ -19,6 +22,9 @@ pub enum Error {
     /// A serde-provoked error has occurred
     #[error("Failed to (de)serialize data: {0}")]
     SerdeError(#[from] serde_json::Error),
+    /// An error has occurred when reading a file
+    #[error("Failed to read a file: {0}")]
+    IoError(#[from] std::io::Error),
     /// An error has occurred when parsing a string from UTF-8 bytes
     #[error("Failed to parse string from UTF-8: {0}")]
     StringError(#[from] FromUtf8Error),
@@ -27,7 +33,267 @@ pub enum Error {
     BackendError(String),
     /// An error has occurred when processing events over stream
     #[error("An error occurred while iterating over stream: {0}")]
-    StreamError(#[from] EventStreamError<reqwest::Error>),
-    #[error("An error occurred while processing request: {0} {1}")]
-    ApiError(String, String),
+    StreamError(#[source] EventStreamError<reqwest::Error>),
+    /// The request would be rejected by the API, e.g. because of an out of range parameter
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    /// An error has occurred while handling a function call requested by the model
+    #[error("An error occurred while calling a function: {0}")]
+    FunctionCallError(String),
+    /// No API key was given, and the environment variable it is read from, `OPENAI_API_KEY` by default, is not set
+    #[error("No API key was provided and the {0} environment variable is not set")]
+    MissingApiKey(String),
+    /// A request or a stream took longer than the configured timeout, whether it is the timeout of the HTTP client or a stream timeout
+    #[error("Timed out: {0}")]
+    Timeout(String),
+    /// The content of the response could not be parsed into the requested type
+    #[error("Failed to parse the response content: {source}. Content: {content}")]
+    ContentParseError {
+        /// The raw content of the response
+        content: String,
+        /// The error that occurred when parsing the content
+        #[source]
+        source: serde_json::Error,
+    },
+    /// The API returned an error status, an error event while streaming, or a response that could not be parsed
+    #[error("The API responded with status {status}: {}", describe_api_error(.body, .error, .path, .source))]
+    ApiError {
+        /// The HTTP status of the response
+        status: StatusCode,
+        /// The raw body of the response, or the data of the event when streaming
+        body: String,
+        /// The error returned by the API, if the body contains one
+        error: Option<Box<ApiErrorBody>>,
+        /// The JSON path of the field that could not be parsed, e.g. `choices[0].message.role`
+        path: Option<String>,
+        /// The error that occurred when parsing the body, if the status was successful
+        #[source]
+        source: Option<serde_json::Error>,
+    },
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        match error.is_timeout() {
+            true => Error::Timeout(error.to_string()),
+            false => Error::ClientError(error),
+        }
+    }
+}
+
+impl From<EventStreamError<reqwest::Error>> for Error {
+    fn from(error: EventStreamError<reqwest::Error>) -> Self {
+        match error {
+            EventStreamError::Transport(error) if error.is_timeout() => error.into(),
+            error => Error::StreamError(error),
+        }
+    }
+}
+
+impl Error {
+    /// Builds an [`Error::ApiError`] from an error response, parsing the error returned by the API when possible
+    pub(crate) fn api_error(status: StatusCode, body: String) -> Self {
+        let error = serde_json::from_str::<ApiErrorEnvelope>(&body)
+            .ok()
+            .map(|envelope| Box::new(envelope.error));
+        Error::ApiError {
+            status,
+            body,
+            error,
+            path: None,
+            source: None,
+        }
+    }
+
+    /// Whether the request may succeed if sent again, which is what the retries of
+    /// [`ClientOptions::with_max_retries`](crate::client::ClientOptions::with_max_retries) check.
+    ///
+    /// | Error | Retryable |
+    /// |---|---|
+    /// | [`Error::ApiError`] with status 408, 409, 429, 500, 502, 503 or 504 | yes |
+    /// | [`Error::ApiError`] with any other status, e.g. 400, 401, 403 or 404, or an unparsable body | no |
+    /// | [`Error::ClientError`] from a failed connection or a connection closed while sending | yes |
+    /// | [`Error::Timeout`] | yes |
+    /// | Anything else, e.g. [`Error::InvalidRequest`] or [`Error::MissingApiKey`] | no |
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::ApiError {
+                status,
+                source: None,
+                ..
+            } => is_retryable_status(*status),
+            Error::ClientError(error) => {
+                #[cfg(not(target_arch = "wasm32"))]
+                if error.is_connect() {
+                    return true;
+                }
+                error.is_request() || error.status().is_some_and(is_retryable_status)
+            }
+            Error::Timeout(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Builds an [`Error::ApiError`] from a [`ResponsePart::Error`](crate::types::ResponsePart::Error) event of a stream, whose response status was successful
+    pub(crate) fn stream_error(error: ApiErrorBody) -> Self {
+        Error::ApiError {
+            status: StatusCode::OK,
+            body: serde_json::to_string(&ApiErrorEnvelope {
+                error: error.clone(),
+            })
+            .unwrap_or_default(),
+            error: Some(Box::new(error)),
+            path: None,
+            source: None,
+        }
+    }
+
+    /// Returns the machine-readable code of the error returned by the API, e.g. `context_length_exceeded`, to handle it programmatically
+    pub fn api_error_code(&self) -> Option<&str> {
+        match self {
+            Error::ApiError {
+                error: Some(error), ..
+            } => error.code.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Builds an [`Error::ApiError`] from a successful response whose body could not be parsed
+    pub(crate) fn parse_error(
+        status: StatusCode,
+        body: String,
+        error: serde_path_to_error::Error<serde_json::Error>,
+    ) -> Self {
+        let path = error.path().to_string();
+        Error::ApiError {
+            status,
+            body,
+            error: None,
+            path: Some(path),
+            source: Some(error.into_inner()),
+        }
+    }
+}
+
+/// Whether a response with this status may succeed if the request is sent again, see [`Error::is_retryable`]
+pub(crate) fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 408 | 409 | 429 | 500 | 502 | 503 | 504)
+}
+
+/// The number of characters of the body shown in the message of an unparsable response
+const BODY_SNIPPET_LENGTH: usize = 200;
+
+/// Describes an [`Error::ApiError`]: the message returned by the API, or what failed to parse with the start of the body
+fn describe_api_error(
+    body: &str,
+    error: &Option<Box<ApiErrorBody>>,
+    path: &Option<String>,
+    source: &Option<serde_json::Error>,
+) -> String {
+    match (error, source) {
+        (Some(error), _) => error.message.clone(),
+        (None, Some(source)) => {
+            let mut snippet: String = body.chars().take(BODY_SNIPPET_LENGTH).collect();
+            if snippet.len() < body.len() {
+                snippet.push('…');
+            }
+            format!(
+                "failed to parse the response at `{}`: {source}. Body: {snippet}",
+                path.as_deref().unwrap_or(".")
+            )
+        }
+        (None, None) => body.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Error;
+    use reqwest::StatusCode;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_error_source() {
+        let serde_error = serde_json::from_str::<u32>("\"not a number\"").unwrap_err();
+        let message = serde_error.to_string();
+
+        let error = Error::from(serde_error);
+        assert_eq!(
+            error.source().map(|source| source.to_string()),
+            Some(message.clone())
+        );
+
+        let error = Error::ApiError {
+            status: StatusCode::OK,
+            body: "\"not a number\"".to_owned(),
+            error: None,
+            path: None,
+            source: Some(serde_json::from_str::<u32>("\"not a number\"").unwrap_err()),
+        };
+        assert_eq!(
+            error.source().map(|source| source.to_string()),
+            Some(message)
+        );
+        assert!(Error::BackendError("failure".to_owned()).source().is_none());
+
+        let reqwest_error = reqwest::Client::new().get("not a url").build().unwrap_err();
+        let message = reqwest_error.to_string();
+        let error = Error::from(reqwest_error);
+        assert!(matches!(error, Error::ClientError(_)));
+        assert_eq!(
+            error.source().map(|source| source.to_string()),
+            Some(message)
+        );
+
+        let error = Error::api_error(StatusCode::SERVICE_UNAVAILABLE, "overloaded".to_owned());
+        assert!(error.source().is_none());
+        assert!(error.to_string().contains("503"));
+    }
+
+    #[test]
+    fn test_error_code() {
+        let body = r#"{
+            "error": {
+                "message": "This model's maximum context length is 128000 tokens. However, your messages resulted in 130532 tokens. Please reduce the length of the messages.",
+                "type": "invalid_request_error",
+                "param": "messages",
+                "code": "context_length_exceeded"
+            }
+        }"#;
+        let error = Error::api_error(StatusCode::BAD_REQUEST, body.to_owned());
+        let Error::ApiError {
+            error: Some(api_error),
+            ..
+        } = &error
+        else {
+            panic!("unexpected error: {error:?}");
+        };
+        assert_eq!(api_error.kind.as_deref(), Some("invalid_request_error"));
+        assert_eq!(api_error.param.as_deref(), Some("messages"));
+        assert_eq!(error.api_error_code(), Some("context_length_exceeded"));
+
+        let body = r#"{"error":{"message":"Rate limit reached","type":"requests","param":null,"code":null}}"#;
+        let error = Error::api_error(StatusCode::TOO_MANY_REQUESTS, body.to_owned());
+        assert_eq!(error.api_error_code(), None);
+
+        let body = r#"{"error":{"message":"Invalid request","code":400}}"#;
+        let error = Error::api_error(StatusCode::BAD_REQUEST, body.to_owned());
+        assert_eq!(error.api_error_code(), Some("400"));
+        assert_eq!(Error::Timeout(String::new()).api_error_code(), None);
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        for status in [429, 500, 502, 503, 504] {
+            let status = StatusCode::from_u16(status).unwrap();
+            assert!(Error::api_error(status, String::new()).is_retryable());
+        }
+        for status in [400, 401, 403, 404, 422] {
+            let status = StatusCode::from_u16(status).unwrap();
+            assert!(!Error::api_error(status, String::new()).is_retryable());
+        }
+        assert!(Error::Timeout("no response".to_owned()).is_retryable());
+        assert!(!Error::InvalidRequest("bad".to_owned()).is_retryable());
+        assert!(!Error::MissingApiKey("OPENAI_API_KEY".to_owned()).is_retryable());
+    }
 }
@@ -0,0 +1,170 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+
+use bytes::Bytes;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Body, Method};
+use serde::{Deserialize, Serialize};
+
+use crate::client::{parse_response, ChatGPT, Organization};
+use crate::types::List;
+
+/// A file uploaded to the API
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileObject {
+    /// Identifier of the file, e.g. `file-abc123`, to reference it in other endpoints
+    pub id: String,
+    /// Size of the file, in bytes
+    pub bytes: u64,
+    /// Unix timestamp (in seconds) of when the file was uploaded
+    pub created_at: u64,
+    /// Name of the file
+    #[serde(default)]
+    pub filename: String,
+    /// What the file is used for
+    pub purpose: FilePurpose,
+}
+
+/// What an uploaded file is used for
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum FilePurpose {
+    /// A file searched or read by assistants
+    #[serde(rename = "assistants")]
+    Assistants,
+    /// Output files written by assistants
+    #[serde(rename = "assistants_output")]
+    AssistantsOutput,
+    /// The JSONL input of a batch
+    #[serde(rename = "batch")]
+    Batch,
+    /// The JSONL output or errors of a batch
+    #[serde(rename = "batch_output")]
+    BatchOutput,
+    /// The JSONL training data of a fine-tuning job
+    #[serde(rename = "fine-tune")]
+    FineTune,
+    /// The results of a fine-tuning job
+    #[serde(rename = "fine-tune-results")]
+    FineTuneResults,
+    /// An image given to assistants
+    #[serde(rename = "vision")]
+    Vision,
+    /// A file for any other use
+    #[serde(rename = "user_data")]
+    UserData,
+    /// A purpose unknown to this version of the crate
+    #[serde(other)]
+    Unknown,
+}
+
+impl FilePurpose {
+    /// Returns the name of the purpose, as expected by the API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FilePurpose::Assistants => "assistants",
+            FilePurpose::AssistantsOutput => "assistants_output",
+            FilePurpose::Batch => "batch",
+            FilePurpose::BatchOutput => "batch_output",
+            FilePurpose::FineTune => "fine-tune",
+            FilePurpose::FineTuneResults => "fine-tune-results",
+            FilePurpose::Vision => "vision",
+            FilePurpose::UserData => "user_data",
+            FilePurpose::Unknown => "unknown",
+        }
+    }
+}
+
+/// The response to the deletion of a file
+#[derive(Deserialize)]
+struct Deletion {
+    deleted: bool,
+}
+
+/// Returns the name of a file, sent along with its content in multipart forms
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Builds the part of a multipart form holding the content of a file, without copying it
+pub(crate) fn file_part(filename: &str, content: &Bytes) -> Part {
+    Part::stream(Body::from(content.clone())).file_name(filename.to_owned())
+}
+
+/// The methods of the files endpoints
+impl ChatGPT {
+    /// Uploads a file, read from the disk without blocking, to use it in other endpoints, e.g. as the input of a batch.
+    ///
+    /// Not available on `wasm32`, which has no file system: use [`Self::upload_file_bytes`] there.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn upload_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        purpose: FilePurpose,
+        org: impl Into<Organization>,
+    ) -> crate::Result<FileObject> {
+        let path = path.as_ref();
+        let content = tokio::fs::read(path).await?;
+        self.upload_file_bytes(file_name(path), content, purpose, org)
+            .await
+    }
+
+    /// Uploads the content of a file, named `filename`, e.g. `requests.jsonl`, to use it in other endpoints
+    pub async fn upload_file_bytes<N: Into<String>, B: Into<Bytes>>(
+        &self,
+        filename: N,
+        content: B,
+        purpose: FilePurpose,
+        org: impl Into<Organization>,
+    ) -> crate::Result<FileObject> {
+        let (filename, content, org) = (filename.into(), content.into(), org.into());
+        let url = self.options.endpoint("files")?;
+        let request = || {
+            let form = Form::new()
+                .text("purpose", purpose.as_str())
+                .part("file", file_part(&filename, &content));
+            self.request(Method::POST, url.clone(), org.clone())
+                .multipart(form)
+        };
+        let resp = self.send_rebuilding(request(), |_| Some(request())).await?;
+        parse_response(resp).await
+    }
+
+    /// Returns the uploaded files
+    pub async fn list_files(&self, org: impl Into<Organization>) -> crate::Result<Vec<FileObject>> {
+        let request = self.request(Method::GET, self.options.endpoint("files")?, org.into());
+        let list: List<FileObject> = parse_response(self.send(request).await?).await?;
+        Ok(list.data)
+    }
+
+    /// Returns an uploaded file
+    pub async fn retrieve_file(
+        &self,
+        file_id: &str,
+        org: impl Into<Organization>,
+    ) -> crate::Result<FileObject> {
+        let url = self.options.endpoint(&format!("files/{file_id}"))?;
+        parse_response(
+            self.send(self.request(Method::GET, url, org.into()))
+                .await?,
+        )
+        .await
+    }
+
+    /// Deletes an uploaded file, returning whether it was deleted
+    pub async fn delete_file(
+        &self,
+        file_id: &str,
+        org: impl Into<Organization>,
+    ) -> crate::Result<bool> {
+        let url = self.options.endpoint(&format!("files/{file_id}"))?;
+        let deletion: Deletion = parse_response(
+            self.send(self.request(Method::DELETE, url, org.into()))
+                .await?,
+        )
+        .await?;
+        Ok(deletion.deleted)
+    }
+}
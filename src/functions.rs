@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::err::Error;
+use crate::types::{Message, Tool, ToolCall};
+
+/// A function handler, called with the parsed arguments of a tool call and returning its result
+pub type FunctionHandler = Box<dyn Fn(Value) -> crate::Result<Value> + Send + Sync>;
+
+/// A registry of functions the model may call, used by [`Conversation::send_with_tools`](crate::conversation::Conversation::send_with_tools)
+pub struct FunctionRegistry {
+    functions: BTreeMap<String, (Tool, FunctionHandler)>,
+    max_iterations: usize,
+}
+
+impl FunctionRegistry {
+    /// Constructs an empty registry, allowing up to 10 request iterations per message
+    pub fn new() -> Self {
+        Self {
+            functions: BTreeMap::new(),
+            max_iterations: 10,
+        }
+    }
+
+    /// Sets the maximum number of requests sent for a single message, to prevent infinite tool-calling loops
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Registers a function, described by a JSON schema of its parameters, with the handler called when the model calls it
+    pub fn register<S, F>(
+        &mut self,
+        name: S,
+        description: S,
+        parameters: Value,
+        handler: F,
+    ) -> &mut Self
+    where
+        S: Into<String>,
+        F: Fn(Value) -> crate::Result<Value> + Send + Sync + 'static,
+    {
+        let tool = Tool::function(name, description, parameters);
+        self.functions
+            .insert(tool.function.name.clone(), (tool, Box::new(handler)));
+        self
+    }
+
+    /// Returns the maximum number of requests sent for a single message
+    pub fn max_iterations(&self) -> usize {
+        self.max_iterations
+    }
+
+    /// Returns the tools to send to the model, sorted by name so that the requests are deterministic
+    pub fn tools(&self) -> Vec<Tool> {
+        self.functions
+            .values()
+            .map(|(tool, _)| tool.clone())
+            .collect()
+    }
+
+    /// Calls the handler of a tool call and returns the message holding its result
+    pub fn call(&self, call: &ToolCall) -> crate::Result<Message> {
+        let (_, handler) = self.functions.get(&call.function.name).ok_or_else(|| {
+            Error::FunctionCallError(format!("unknown function `{}`", call.function.name))
+        })?;
+        let arguments: Value = serde_json::from_str(&call.function.arguments)?;
+        let result = handler(arguments)?;
+        Ok(Message::tool_result(call.id.clone(), result.to_string()))
+    }
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for FunctionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FunctionRegistry")
+            .field("functions", &self.functions.keys().collect::<Vec<_>>())
+            .field("max_iterations", &self.max_iterations)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FunctionRegistry;
+    use serde_json::json;
+
+    #[test]
+    fn test_tools_order() {
+        let mut functions = FunctionRegistry::new();
+        for name in ["lookup", "add", "search"] {
+            functions.register(name, "A function", json!({ "type": "object" }), Ok);
+        }
+        let names: Vec<_> = functions
+            .tools()
+            .into_iter()
+            .map(|tool| tool.function.name)
+            .collect();
+        assert_eq!(names, ["add", "lookup", "search"]);
+    }
+}
@@ -0,0 +1,84 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The future returned by the hooks of an [`Interceptor`]
+pub type InterceptorFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// A hook called around every request sent by the client, e.g. for logging, metrics or header injection.
+///
+/// Interceptors are registered with [`ClientOptions::with_interceptor`](crate::client::ClientOptions::with_interceptor)
+/// and called in the order they were registered, each hook awaited before the next one. Retried requests go through them
+/// again. They only see the `http` parts of the requests and responses, not their bodies.
+pub trait Interceptor: Send + Sync {
+    /// Called right before the request is sent, with the headers of the crate already set. The request can be modified
+    fn on_request<'a>(&'a self, _request: &'a mut http::request::Parts) -> InterceptorFuture<'a> {
+        Box::pin(async {})
+    }
+
+    /// Called once the status and headers of the response are received, before its body is read
+    fn on_response<'a>(&'a self, _response: &'a http::response::Parts) -> InterceptorFuture<'a> {
+        Box::pin(async {})
+    }
+}
+
+/// The interceptors registered on the client options, compared by identity
+#[derive(Clone, Default)]
+pub(crate) struct Interceptors(Vec<Arc<dyn Interceptor>>);
+
+impl Interceptors {
+    /// Registers an interceptor after the others
+    pub(crate) fn push(&mut self, interceptor: Arc<dyn Interceptor>) {
+        self.0.push(interceptor);
+    }
+
+    /// Calls the interceptors before the request is sent, returning the request they modified
+    pub(crate) async fn on_request(
+        &self,
+        request: reqwest::Request,
+    ) -> crate::Result<reqwest::Request> {
+        if self.0.is_empty() {
+            return Ok(request);
+        }
+        let request: http::Request<reqwest::Body> = request.try_into()?;
+        let (mut parts, body) = request.into_parts();
+        for interceptor in &self.0 {
+            interceptor.on_request(&mut parts).await;
+        }
+        Ok(http::Request::from_parts(parts, body).try_into()?)
+    }
+
+    /// Calls the interceptors once the response is received
+    pub(crate) async fn on_response(&self, response: &reqwest::Response) {
+        if self.0.is_empty() {
+            return;
+        }
+        let mut parts = http::Response::new(()).into_parts().0;
+        parts.status = response.status();
+        parts.version = response.version();
+        parts.headers = response.headers().clone();
+        for interceptor in &self.0 {
+            interceptor.on_response(&parts).await;
+        }
+    }
+}
+
+impl fmt::Debug for Interceptors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} interceptors", self.0.len())
+    }
+}
+
+impl PartialEq for Interceptors {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().zip(&other.0).all(|(a, b)| Arc::ptr_eq(a, b))
+    }
+}
+
+impl PartialOrd for Interceptors {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (self == other).then_some(Ordering::Equal)
+    }
+}
@@ -1,3 +1,9 @@
-pub use crate::client::{ChatGPT, ClientOptions};
-pub use crate::types::{ConversationResponse, Message, ResponsePart};
+pub use crate::client::{ChatGPT, ClientOptions, Organization};
+pub use crate::conversation::Conversation;
+pub use crate::functions::FunctionRegistry;
+pub use crate::stream::ChatStream;
+pub use crate::types::{
+    CompletionOptions, Content, ContentPart, ConversationResponse, FinishReason, IntoMessages,
+    Message, Model, Preset, ResponseFormat, ResponsePart, Role, Tool, ToolCall,
+};
 pub use futures_util::{Stream, StreamExt};
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use crate::types::{Model, Usage};
+
+/// Price of a model, in dollars per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct ModelPricing {
+    /// Price of a million prompt tokens
+    pub input: f64,
+    /// Price of a million completion tokens
+    pub output: f64,
+}
+
+impl ModelPricing {
+    /// Constructs a new model price, in dollars per million tokens
+    pub fn new(input: f64, output: f64) -> Self {
+        Self { input, output }
+    }
+}
+
+/// A table of model prices, used to estimate the cost of requests.
+///
+/// The default table holds the public OpenAI prices of the known [`Model`]s at the time of release.
+/// Since prices change, they can be overridden, and custom or Azure deployments can be added with [`Self::with_price`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pricing {
+    prices: HashMap<Model, ModelPricing>,
+}
+
+impl Pricing {
+    /// Constructs an empty pricing table
+    pub fn new() -> Self {
+        Self {
+            prices: HashMap::new(),
+        }
+    }
+
+    /// Sets the price of a model, overriding the previous one
+    pub fn with_price<M: Into<Model>>(mut self, model: M, pricing: ModelPricing) -> Self {
+        self.insert(model, pricing);
+        self
+    }
+
+    /// Sets the price of a model, overriding the previous one
+    pub fn insert<M: Into<Model>>(&mut self, model: M, pricing: ModelPricing) {
+        self.prices.insert(model.into(), pricing);
+    }
+
+    /// Returns the price of a model, if it is in the table
+    pub fn get(&self, model: &Model) -> Option<ModelPricing> {
+        self.prices.get(model).copied()
+    }
+
+    /// Estimates the cost in dollars of a request to the model, or `None` if the model isn't in the table
+    pub fn cost(&self, usage: &Usage, model: &Model) -> Option<f64> {
+        let pricing = self.get(model)?;
+        Some(
+            (usage.prompt_tokens as f64 * pricing.input
+                + usage.completion_tokens as f64 * pricing.output)
+                / 1_000_000.0,
+        )
+    }
+}
+
+impl Default for Pricing {
+    fn default() -> Self {
+        Self::new()
+            .with_price(Model::Gpt4o, ModelPricing::new(2.5, 10.0))
+            .with_price(Model::Gpt4oMini, ModelPricing::new(0.15, 0.6))
+            .with_price(Model::Gpt4Turbo, ModelPricing::new(10.0, 30.0))
+            .with_price(Model::Gpt4, ModelPricing::new(30.0, 60.0))
+            .with_price(Model::Gpt35Turbo, ModelPricing::new(0.5, 1.5))
+            .with_price(Model::O1, ModelPricing::new(15.0, 60.0))
+            .with_price(Model::O1Mini, ModelPricing::new(1.1, 4.4))
+            .with_price(Model::O3Mini, ModelPricing::new(1.1, 4.4))
+    }
+}
+
+impl Usage {
+    /// Estimates the cost in dollars of the request with the default [`Pricing`], or `None` if the model has no known price
+    pub fn estimated_cost(&self, model: &Model) -> Option<f64> {
+        self.estimated_cost_with(model, &Pricing::default())
+    }
+
+    /// Estimates the cost in dollars of the request with a custom [`Pricing`], or `None` if the model isn't in the table
+    pub fn estimated_cost_with(&self, model: &Model, pricing: &Pricing) -> Option<f64> {
+        pricing.cost(self, model)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ModelPricing, Pricing};
+    use crate::types::{Model, Usage};
+
+    #[test]
+    fn test_estimated_cost() {
+        let usage = Usage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 500_000,
+            total_tokens: 1_500_000,
+            ..Default::default()
+        };
+        assert_eq!(usage.estimated_cost(&Model::Gpt4o), Some(7.5));
+        assert_eq!(usage.estimated_cost(&"my-deployment".into()), None);
+
+        let pricing = Pricing::default().with_price("my-deployment", ModelPricing::new(1.0, 2.0));
+        assert_eq!(
+            usage.estimated_cost_with(&"my-deployment".into(), &pricing),
+            Some(2.0)
+        );
+    }
+}
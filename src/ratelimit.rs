@@ -0,0 +1,203 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::header::HeaderMap;
+
+/// The rate limits of the account, as returned in the `x-ratelimit-*` headers of a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RateLimits {
+    /// Maximum number of requests allowed before the limit resets
+    pub limit_requests: Option<u64>,
+    /// Maximum number of tokens allowed before the limit resets
+    pub limit_tokens: Option<u64>,
+    /// Number of requests left before the limit resets
+    pub remaining_requests: Option<u64>,
+    /// Number of tokens left before the limit resets
+    pub remaining_tokens: Option<u64>,
+    /// Time until the request limit resets to its maximum
+    pub reset_requests: Option<Duration>,
+    /// Time until the token limit resets to its maximum
+    pub reset_tokens: Option<Duration>,
+}
+
+impl RateLimits {
+    /// Parses the rate limits from the headers of a response, if it has any
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        let number = |name: &str| header(name).and_then(|value| value.parse().ok());
+        let limits = Self {
+            limit_requests: number("x-ratelimit-limit-requests"),
+            limit_tokens: number("x-ratelimit-limit-tokens"),
+            remaining_requests: number("x-ratelimit-remaining-requests"),
+            remaining_tokens: number("x-ratelimit-remaining-tokens"),
+            reset_requests: header("x-ratelimit-reset-requests").and_then(parse_reset),
+            reset_tokens: header("x-ratelimit-reset-tokens").and_then(parse_reset),
+        };
+        (limits != Self::default()).then_some(limits)
+    }
+
+    /// How long to wait between requests to spread the remaining budget until the limits reset.
+    ///
+    /// This is zero until less than a tenth of either budget remains.
+    pub fn pacing_delay(&self) -> Duration {
+        let pace = |limit: Option<u64>, remaining: Option<u64>, reset: Option<Duration>| match (
+            limit, remaining, reset,
+        ) {
+            (Some(limit), Some(remaining), Some(reset)) if remaining * 10 <= limit => {
+                reset / (remaining as u32).saturating_add(1)
+            }
+            _ => Duration::ZERO,
+        };
+        pace(
+            self.limit_requests,
+            self.remaining_requests,
+            self.reset_requests,
+        )
+        .max(pace(
+            self.limit_tokens,
+            self.remaining_tokens,
+            self.reset_tokens,
+        ))
+    }
+}
+
+/// Parses a reset duration such as `1s`, `6m0s`, `20ms` or `1h2m3.5s`
+fn parse_reset(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_len].parse().ok()?;
+        rest = &rest[number_len..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        total += number
+            * match &rest[..unit_len] {
+                "ms" => 0.001,
+                "s" => 1.0,
+                "m" => 60.0,
+                "h" => 3600.0,
+                _ => return None,
+            };
+        rest = &rest[unit_len..];
+    }
+    // Out of range values, e.g. from a misbehaving proxy, are ignored rather than overflowing
+    Duration::try_from_secs_f64(total).ok()
+}
+
+/// Paces the requests of a client from the rate limits of its latest response
+#[derive(Debug, Default)]
+pub(crate) struct Throttle {
+    state: Mutex<ThrottleState>,
+}
+
+#[derive(Debug, Default)]
+struct ThrottleState {
+    limits: Option<RateLimits>,
+    interval: Duration,
+    next: Option<Instant>,
+}
+
+impl Throttle {
+    /// Records the rate limits of a response, scheduling the next request when the budget is low.
+    ///
+    /// Only the limits are recorded on `wasm32`, where there is no clock to schedule requests with
+    pub(crate) fn record(&self, limits: RateLimits) {
+        let mut state = self.state.lock().unwrap();
+        state.limits = Some(limits);
+        if cfg!(target_arch = "wasm32") {
+            return;
+        }
+        let now = Instant::now();
+        state.interval = limits.pacing_delay();
+        state.next = match state.next {
+            _ if state.interval.is_zero() => None,
+            Some(next) => Some(next.max(now + state.interval)),
+            None => Some(now + state.interval),
+        };
+    }
+
+    /// Returns the latest recorded rate limits
+    pub(crate) fn limits(&self) -> Option<RateLimits> {
+        self.state.lock().unwrap().limits
+    }
+
+    /// Reserves the next slot to send a request in, returning how long to wait for it
+    pub(crate) fn reserve(&self) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let Some(next) = state.next else {
+            return Duration::ZERO;
+        };
+        let slot = next.max(now);
+        state.next = Some(slot + state.interval);
+        slot - now
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_reset, RateLimits, Throttle};
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_rate_limits() {
+        assert_eq!(parse_reset("20ms"), Some(Duration::from_millis(20)));
+        assert_eq!(parse_reset("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(
+            parse_reset("1h2m3.5s"),
+            Some(Duration::from_millis(3_723_500))
+        );
+        assert_eq!(parse_reset("soon"), None);
+        assert_eq!(parse_reset("99999999999999999999h"), None);
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(RateLimits::from_headers(&headers), None);
+        headers.insert(
+            "x-ratelimit-limit-requests",
+            HeaderValue::from_static("100"),
+        );
+        headers.insert(
+            "x-ratelimit-remaining-requests",
+            HeaderValue::from_static("50"),
+        );
+        headers.insert("x-ratelimit-reset-requests", HeaderValue::from_static("1s"));
+        let limits = RateLimits::from_headers(&headers).unwrap();
+        assert_eq!(limits.remaining_requests, Some(50));
+        assert_eq!(limits.pacing_delay(), Duration::ZERO);
+
+        headers.insert(
+            "x-ratelimit-remaining-requests",
+            HeaderValue::from_static("1"),
+        );
+        let limits = RateLimits::from_headers(&headers).unwrap();
+        assert_eq!(limits.pacing_delay(), Duration::from_millis(500));
+
+        headers.insert(
+            "x-ratelimit-reset-requests",
+            HeaderValue::from_static("99999999999999999999h"),
+        );
+        let limits = RateLimits::from_headers(&headers).unwrap();
+        assert_eq!(limits.reset_requests, None);
+    }
+
+    #[test]
+    fn test_throttle_spreads_requests() {
+        let throttle = Throttle::default();
+        assert_eq!(throttle.reserve(), Duration::ZERO);
+        throttle.record(RateLimits {
+            limit_requests: Some(100),
+            remaining_requests: Some(0),
+            reset_requests: Some(Duration::from_millis(100)),
+            ..Default::default()
+        });
+        let first = throttle.reserve();
+        let second = throttle.reserve();
+        assert!(first > Duration::from_millis(50) && first <= Duration::from_millis(100));
+        assert!(second > first + Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,515 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_util::stream::FusedStream;
+use futures_util::{Stream, StreamExt};
+
+use crate::err::Error;
+use crate::types::{
+    Content, ConversationChoice, ConversationResponse, DeltaChunk, Logprobs, Message, ObjectKind,
+    ResponsePart, Role, ToolCall, ToolCallFunction, Usage,
+};
+
+/// Reassembles the tool calls of a streamed response from their fragments.
+///
+/// The ID and function name of a tool call arrive in its first fragment, while the JSON arguments are split across many of them.
+/// Fragments are matched by their index, so parallel tool calls interleaved across chunks are reassembled correctly.
+/// Use one assembler per choice of the response.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ToolCallAssembler {
+    calls: BTreeMap<usize, ToolCall>,
+}
+
+impl ToolCallAssembler {
+    /// Constructs an empty assembler
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the tool call fragments of a delta
+    pub fn push(&mut self, delta: &DeltaChunk) {
+        for fragment in delta.tool_calls.iter().flatten() {
+            let call = self
+                .calls
+                .entry(fragment.index)
+                .or_insert_with(|| ToolCall {
+                    id: String::new(),
+                    kind: "function".to_owned(),
+                    function: ToolCallFunction {
+                        name: String::new(),
+                        arguments: String::new(),
+                    },
+                });
+            if let Some(id) = &fragment.id {
+                call.id.clone_from(id);
+            }
+            if let Some(kind) = &fragment.kind {
+                call.kind.clone_from(kind);
+            }
+            if let Some(function) = &fragment.function {
+                if let Some(name) = &function.name {
+                    call.function.name.push_str(name);
+                }
+                if let Some(arguments) = &function.arguments {
+                    call.function.arguments.push_str(arguments);
+                }
+            }
+        }
+    }
+
+    /// Whether no tool call fragment has been received yet
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// Returns the reassembled tool calls, ordered by index. Call it once the stream has ended
+    pub fn finish(self) -> Vec<ToolCall> {
+        self.calls.into_values().collect()
+    }
+}
+
+/// A streamed response, as returned by [`ChatGPT::send_message_streaming`](crate::client::ChatGPT::send_message_streaming),
+/// which keeps track of its state while it is consumed.
+///
+/// It yields the parts of the response like the stream it wraps, and tells whether [`ResponsePart::Done`] was received
+/// and what content the first choice has accumulated so far, e.g. for consumers multiplexing it with `tokio::select!`.
+/// It is fused: once the wrapped stream has ended, it keeps returning `None`.
+pub struct ChatStream {
+    parts: Pin<Box<dyn Stream<Item = crate::Result<ResponsePart>> + Send>>,
+    content: String,
+    done: bool,
+    terminated: bool,
+}
+
+impl ChatStream {
+    /// Wraps a stream of response parts
+    pub fn new<S>(parts: S) -> Self
+    where
+        S: Stream<Item = crate::Result<ResponsePart>> + Send + 'static,
+    {
+        Self {
+            parts: Box::pin(parts),
+            content: String::new(),
+            done: false,
+            terminated: false,
+        }
+    }
+
+    /// Whether [`ResponsePart::Done`] was received, i.e. the response is complete
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Returns the content of the first choice received so far
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+}
+
+impl Stream for ChatStream {
+    type Item = crate::Result<ResponsePart>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let part = futures_util::ready!(self.parts.as_mut().poll_next(cx));
+        match &part {
+            Some(Ok(ResponsePart::Chunk(chunk))) => {
+                let content = chunk
+                    .choices
+                    .iter()
+                    .filter(|choice| choice.index == 0)
+                    .filter_map(|choice| choice.delta.content.as_deref());
+                for content in content {
+                    self.content.push_str(content);
+                }
+            }
+            Some(Ok(ResponsePart::Done)) => self.done = true,
+            Some(Ok(ResponsePart::Error(_)) | Err(_)) => {}
+            None => self.terminated = true,
+        }
+        Poll::Ready(part)
+    }
+}
+
+impl FusedStream for ChatStream {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl fmt::Debug for ChatStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChatStream")
+            .field("content", &self.content)
+            .field("done", &self.done)
+            .field("terminated", &self.terminated)
+            .finish()
+    }
+}
+
+/// Collects a streamed response into the response the API would have returned without streaming.
+///
+/// The deltas of the choices requested with [`CompletionOptions::n`](crate::types::CompletionOptions::n), which are interleaved, are told
+/// apart by their index. The content and tool calls of every choice are reassembled from their deltas, and its [`FinishReason`](crate::types::FinishReason) taken from its last chunk,
+/// e.g. to detect an answer truncated by `max_tokens`.
+/// [`ConversationResponse::usage`] is the usage sent in the last chunk when [`StreamOptions::include_usage`](crate::types::StreamOptions::include_usage) is set,
+/// and is left zeroed otherwise. A [`ResponsePart::Error`] event fails the collection with an [`Error::ApiError`].
+pub async fn collect_response<S>(stream: S) -> crate::Result<ConversationResponse>
+where
+    S: Stream<Item = crate::Result<ResponsePart>>,
+{
+    futures_util::pin_mut!(stream);
+    let mut response = ConversationResponse {
+        id: String::new(),
+        object: ObjectKind::ChatCompletion,
+        created: 0,
+        choices: Vec::new(),
+        usage: Usage::default(),
+        service_tier: None,
+        provider: None,
+    };
+    let mut choices: BTreeMap<usize, (ConversationChoice, ToolCallAssembler)> = BTreeMap::new();
+    while let Some(part) = stream.next().await {
+        let chunk = match part? {
+            ResponsePart::Chunk(chunk) => chunk,
+            ResponsePart::Done => break,
+            ResponsePart::Error(error) => return Err(Error::stream_error(error)),
+        };
+        response.id = chunk.id;
+        response.created = chunk.created;
+        if chunk.provider.is_some() {
+            response.provider = chunk.provider;
+        }
+        if let Some(usage) = chunk.usage {
+            response.usage = usage;
+        }
+        for choice in chunk.choices {
+            let (collected, tool_calls) = choices.entry(choice.index).or_insert_with(|| {
+                (
+                    ConversationChoice {
+                        index: choice.index as u64,
+                        message: Message::default(),
+                        finish_reason: None,
+                        stop_reason: None,
+                        logprobs: None,
+                        content_filter_results: None,
+                    },
+                    ToolCallAssembler::new(),
+                )
+            });
+            if choice.finish_reason.is_some() {
+                collected.finish_reason = choice.finish_reason;
+            }
+            if choice.stop_reason.is_some() {
+                collected.stop_reason = choice.stop_reason;
+            }
+            if choice.content_filter_results.is_some() {
+                collected.content_filter_results = choice.content_filter_results;
+            }
+            if let Some(logprobs) = choice.logprobs {
+                collected
+                    .logprobs
+                    .get_or_insert_with(Logprobs::default)
+                    .append(logprobs);
+            }
+            let message = &mut collected.message;
+            // The role is only sent in the first delta, and some backends repeat it empty in the next ones
+            match &choice.delta.role {
+                Some(role) if message.role.is_empty() => message.role.clone_from(role),
+                _ => {}
+            }
+            if let Some(content) = &choice.delta.content {
+                message
+                    .content
+                    .get_or_insert_with(Content::default)
+                    .push_str(content);
+            }
+            if let Some(delta) = &choice.delta.function_call {
+                let call = message.function_call.get_or_insert_with(Default::default);
+                call.name
+                    .push_str(delta.name.as_deref().unwrap_or_default());
+                call.arguments
+                    .push_str(delta.arguments.as_deref().unwrap_or_default());
+            }
+            if let Some(refusal) = &choice.delta.refusal {
+                message
+                    .refusal
+                    .get_or_insert_with(String::new)
+                    .push_str(refusal);
+            }
+            tool_calls.push(&choice.delta);
+        }
+    }
+    response.choices = choices
+        .into_values()
+        .map(|(mut choice, tool_calls)| {
+            if choice.message.role.is_empty() {
+                choice.message.role = Role::Assistant.as_str().to_owned();
+            }
+            if !tool_calls.is_empty() {
+                choice.message.tool_calls = Some(tool_calls.finish());
+            }
+            choice
+        })
+        .collect();
+    Ok(response)
+}
+
+/// Collects a streamed response like [`collect_response`], calling `on_delta` with every content delta of the first choice as it arrives.
+///
+/// This covers displaying the answer while it is generated and keeping the whole of it, along with its
+/// [`FinishReason`](crate::types::FinishReason) and usage, once the stream has ended.
+pub async fn collect_with_callback<S, F>(
+    stream: S,
+    mut on_delta: F,
+) -> crate::Result<ConversationResponse>
+where
+    S: Stream<Item = crate::Result<ResponsePart>>,
+    F: FnMut(&str),
+{
+    collect_response(stream.inspect(|part| {
+        if let Ok(ResponsePart::Chunk(chunk)) = part {
+            chunk
+                .choices
+                .iter()
+                .filter(|choice| choice.index == 0)
+                .filter_map(|choice| choice.delta.content.as_deref())
+                .for_each(&mut on_delta);
+        }
+    }))
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::{collect_response, collect_with_callback, ChatStream, ToolCallAssembler};
+    use crate::types::{DeltaChunk, FinishReason, ResponsePart};
+    use futures_util::stream::{self, FusedStream};
+    use futures_util::StreamExt;
+    use serde_json::json;
+
+    /// Builds a chunk holding the delta of a choice, ending it if a finish reason is given
+    fn chunk(
+        index: usize,
+        delta: serde_json::Value,
+        finish_reason: Option<&str>,
+    ) -> crate::Result<ResponsePart> {
+        serde_json::from_value(json!({
+            "id": "chatcmpl-123",
+            "created": 1677652288,
+            "model": "gpt-4o",
+            "choices": [{ "index": index, "delta": delta, "finish_reason": finish_reason }],
+        }))
+        .map(ResponsePart::Chunk)
+        .map_err(Into::into)
+    }
+
+    #[test]
+    fn test_interleaved_tool_calls() -> crate::Result<()> {
+        let deltas = [
+            json!({ "tool_calls": [{ "index": 0, "id": "call_a", "type": "function", "function": { "name": "get_weather", "arguments": "" } }] }),
+            json!({ "tool_calls": [{ "index": 1, "id": "call_b", "type": "function", "function": { "name": "get_time", "arguments": "" } }] }),
+            json!({ "tool_calls": [{ "index": 0, "function": { "arguments": "{\"city\":" } }] }),
+            json!({ "tool_calls": [{ "index": 1, "function": { "arguments": "{\"zone\":\"UTC\"}" } }] }),
+            json!({ "tool_calls": [{ "index": 0, "function": { "arguments": "\"Paris\"}" } }] }),
+            json!({ "content": null }),
+        ];
+        let mut assembler = ToolCallAssembler::new();
+        for delta in deltas {
+            assembler.push(&serde_json::from_value::<DeltaChunk>(delta)?);
+        }
+        let calls = assembler.finish();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].id, "call_a");
+        assert_eq!(calls[0].function.name, "get_weather");
+        assert_eq!(calls[0].function.arguments, r#"{"city":"Paris"}"#);
+        assert_eq!(calls[1].id, "call_b");
+        assert_eq!(calls[1].function.name, "get_time");
+        assert_eq!(calls[1].function.arguments, r#"{"zone":"UTC"}"#);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_collect_response() -> crate::Result<()> {
+        // The usage comes in a last chunk without choices
+        let usage = serde_json::from_value(json!({
+            "id": "chatcmpl-123",
+            "choices": [],
+            "usage": { "prompt_tokens": 9, "completion_tokens": 3, "total_tokens": 12 },
+        }))?;
+        let parts = vec![
+            chunk(0, json!({ "role": "assistant", "content": "Hello" }), None),
+            chunk(0, json!({ "content": " there!" }), Some("length")),
+            Ok(ResponsePart::Chunk(usage)),
+            Ok(ResponsePart::Done),
+        ];
+        let response = collect_response(stream::iter(parts)).await?;
+        assert_eq!(response.id, "chatcmpl-123");
+        assert_eq!(response.choices.len(), 1);
+        assert_eq!(
+            response.choices[0].message.content,
+            Some("Hello there!".into())
+        );
+        assert_eq!(
+            response.choices[0].finish_reason,
+            Some(FinishReason::Length)
+        );
+        assert_eq!(response.usage.total_tokens, 12);
+        assert!(!response.usage.estimated);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_collect_with_callback() -> crate::Result<()> {
+        let parts = vec![
+            chunk(0, json!({ "content": "Hello" }), None),
+            chunk(1, json!({ "content": "Hi" }), None),
+            chunk(0, json!({ "content": " there!" }), None),
+            Ok(ResponsePart::Done),
+        ];
+        let mut deltas = Vec::new();
+        let response =
+            collect_with_callback(stream::iter(parts), |delta| deltas.push(delta.to_owned()))
+                .await?;
+        assert_eq!(deltas, ["Hello", " there!"]);
+        assert_eq!(
+            response.choices[0].message.content,
+            Some("Hello there!".into())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_collect_role_transitions() -> crate::Result<()> {
+        let parts = vec![
+            chunk(
+                0,
+                json!({ "role": "assistant", "content": "Let me check" }),
+                None,
+            ),
+            chunk(0, json!({ "content": " the weather." }), None),
+            chunk(
+                0,
+                json!({ "role": "", "content": null, "tool_calls": [{
+                    "index": 0,
+                    "id": "call_1",
+                    "type": "function",
+                    "function": { "name": "get_weather", "arguments": "" },
+                }] }),
+                None,
+            ),
+            chunk(
+                0,
+                json!({ "tool_calls": [{ "index": 0, "function": { "arguments": "{\"city\":\"Paris\"}" } }] }),
+                None,
+            ),
+            Ok(ResponsePart::Done),
+        ];
+        let response = collect_response(stream::iter(parts)).await?;
+        let message = &response.choices[0].message;
+        assert_eq!(message.role, "assistant");
+        assert_eq!(message.content, Some("Let me check the weather.".into()));
+        let calls = message.tool_calls.as_ref().unwrap();
+        assert_eq!(calls[0].function.name, "get_weather");
+        assert_eq!(calls[0].function.arguments, r#"{"city":"Paris"}"#);
+
+        let parts = vec![
+            chunk(
+                0,
+                json!({ "role": "assistant", "function_call": { "name": "get_weather", "arguments": "" } }),
+                None,
+            ),
+            chunk(
+                0,
+                json!({ "function_call": { "arguments": "{\"city\":" } }),
+                None,
+            ),
+            chunk(
+                0,
+                json!({ "function_call": { "arguments": "\"Paris\"}" } }),
+                None,
+            ),
+            Ok(ResponsePart::Done),
+        ];
+        let response = collect_response(stream::iter(parts)).await?;
+        let call = response.choices[0].message.function_call.as_ref().unwrap();
+        assert_eq!(call.name, "get_weather");
+        assert_eq!(call.arguments, r#"{"city":"Paris"}"#);
+
+        let parts = vec![
+            chunk(0, json!({ "content": "Hi" }), None),
+            Ok(ResponsePart::Done),
+        ];
+        let response = collect_response(stream::iter(parts)).await?;
+        assert_eq!(response.choices[0].message.role, "assistant");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_collect_interleaved_choices() -> crate::Result<()> {
+        let parts = vec![
+            chunk(1, json!({ "role": "assistant", "content": "" }), None),
+            chunk(0, json!({ "role": "assistant", "content": "Red" }), None),
+            chunk(1, json!({ "content": "Dark" }), None),
+            chunk(0, json!({ "content": " apple" }), None),
+            chunk(1, json!({ "content": " blue" }), None),
+            chunk(1, json!({}), Some("stop")),
+            chunk(0, json!({ "content": " pie" }), None),
+            chunk(0, json!({}), Some("length")),
+            Ok(ResponsePart::Done),
+        ];
+        let response = collect_response(stream::iter(parts)).await?;
+        assert_eq!(response.choices.len(), 2);
+        let choices: Vec<_> = response
+            .choices
+            .iter()
+            .map(|choice| {
+                (
+                    choice.index,
+                    choice.message.content.as_ref().unwrap().text().into_owned(),
+                    choice.finish_reason.unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            choices,
+            [
+                (0, "Red apple pie".to_owned(), FinishReason::Length),
+                (1, "Dark blue".to_owned(), FinishReason::Stop),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_state() -> crate::Result<()> {
+        let parts = vec![
+            chunk(0, json!({ "content": "Hello" }), None),
+            chunk(1, json!({ "content": "Hi" }), None),
+            chunk(0, json!({ "content": " there!" }), None),
+            Ok(ResponsePart::Done),
+        ];
+        let mut stream = ChatStream::new(stream::iter(parts));
+        assert!(!stream.is_done());
+        assert_eq!(stream.content(), "");
+
+        stream.next().await.unwrap()?;
+        assert_eq!(stream.content(), "Hello");
+        stream.next().await.unwrap()?;
+        stream.next().await.unwrap()?;
+        assert_eq!(stream.content(), "Hello there!");
+        assert!(!stream.is_done());
+
+        assert_eq!(stream.next().await.unwrap()?, ResponsePart::Done);
+        assert!(stream.is_done());
+        assert!(!stream.is_terminated());
+        assert!(stream.next().await.is_none());
+        assert!(stream.is_terminated());
+        assert!(stream.next().await.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+use tiktoken_rs::CoreBPE;
+
+use crate::types::{Message, Model, Usage};
+
+/// Tokens added to every message by the chat format, on top of its content
+const TOKENS_PER_MESSAGE: usize = 3;
+/// Tokens priming the reply of the assistant
+const TOKENS_PER_REPLY: usize = 3;
+
+/// Counts the tokens of the text with the tokenizer of the model.
+///
+/// Models unknown to the tokenizer, e.g. [`Model::Custom`] ones, are assumed to use the `o200k_base` encoding of the gpt-4o family.
+pub fn count_tokens(model: &Model, text: &str) -> usize {
+    with_bpe(model, |bpe| bpe.encode_with_special_tokens(text).len())
+}
+
+/// Counts the tokens of the messages as they are sent to the model, including the overhead of the chat format
+pub fn count_message_tokens(model: &Model, messages: &[Message]) -> usize {
+    with_bpe(model, |bpe| {
+        messages
+            .iter()
+            .map(|message| {
+                TOKENS_PER_MESSAGE
+                    + bpe.encode_with_special_tokens(&message.role).len()
+                    + message.content.as_ref().map_or(0, |content| {
+                        bpe.encode_with_special_tokens(&content.text()).len()
+                    })
+            })
+            .sum::<usize>()
+            + TOKENS_PER_REPLY
+    })
+}
+
+/// Estimates the usage of a request from the prompt messages and the generated ones.
+///
+/// The estimate is marked with [`Usage::estimated`], as it may differ slightly from the numbers the API bills.
+pub fn estimate_usage(model: &Model, prompt: &[Message], completions: &[Message]) -> Usage {
+    let prompt_tokens = count_message_tokens(model, prompt);
+    let completion_tokens = completions
+        .iter()
+        .filter_map(|message| message.content.as_ref())
+        .map(|content| count_tokens(model, &content.text()))
+        .sum();
+    Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+        estimated: true,
+        ..Default::default()
+    }
+}
+
+/// Runs the closure with the shared tokenizer of the model
+fn with_bpe<T>(model: &Model, f: impl FnOnce(&CoreBPE) -> T) -> T {
+    let bpe = match get_tokenizer(model.id()) {
+        Some(Tokenizer::Cl100kBase) => tiktoken_rs::cl100k_base_singleton(),
+        Some(Tokenizer::P50kBase) => tiktoken_rs::p50k_base_singleton(),
+        Some(Tokenizer::P50kEdit) => tiktoken_rs::p50k_edit_singleton(),
+        Some(Tokenizer::R50kBase) | Some(Tokenizer::Gpt2) => tiktoken_rs::r50k_base_singleton(),
+        Some(Tokenizer::O200kBase) | None => tiktoken_rs::o200k_base_singleton(),
+    };
+    let bpe = bpe.lock();
+    f(&bpe)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{count_message_tokens, count_tokens, estimate_usage};
+    use crate::types::{CompletionOptions, Message, Model};
+
+    #[test]
+    fn test_fill_max_tokens() -> crate::Result<()> {
+        let messages = [Message::from("Hello world")];
+        let options = CompletionOptions::default().fill_max_tokens(&messages, &Model::Gpt4)?;
+        let prompt_tokens = count_message_tokens(&Model::Gpt4, &messages);
+        assert_eq!(options.max_tokens, Some(8_192 - prompt_tokens - 32));
+        let options = CompletionOptions::default().fill_max_tokens(&messages, &Model::Gpt4o)?;
+        assert_eq!(options.max_tokens, Some(16_384));
+
+        let long = [Message::from("hello ".repeat(8_200))];
+        assert!(CompletionOptions::default()
+            .fill_max_tokens(&long, &Model::Gpt4)
+            .is_err());
+        assert!(CompletionOptions::default()
+            .fill_max_tokens(&messages, &Model::from("llama3.2"))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_estimate_usage() {
+        let model = Model::Gpt4o;
+        assert_eq!(count_tokens(&model, "Hello world"), 2);
+        let usage = estimate_usage(
+            &model,
+            &[Message::from("Hello world")],
+            &[Message::from("Hi there")],
+        );
+        assert_eq!(usage.prompt_tokens, 3 + 1 + 2 + 3);
+        assert_eq!(usage.completion_tokens, 2);
+        assert_eq!(usage.total_tokens, 11);
+        assert!(usage.estimated);
+    }
+}
@@ -0,0 +1,91 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+
+/// The body of a response returned by a [`HttpClient`], received in chunks, e.g. the events of a streamed completion
+pub type ResponseBody = Pin<Box<dyn Stream<Item = crate::Result<Bytes>> + Send>>;
+
+/// The response to a request, returned by [`HttpClient::execute`] once its status and headers are received
+pub type ResponseFuture<'a> =
+    Pin<Box<dyn Future<Output = crate::Result<http::Response<ResponseBody>>> + Send + 'a>>;
+
+/// The transport sending the requests of the client, which is the built-in [`reqwest::Client`] by default.
+///
+/// Set one with [`ClientOptions::with_http_client`](crate::client::ClientOptions::with_http_client) to send the requests
+/// with another HTTP stack, or to answer them without any network, e.g. in unit tests. It only deals with the `http` types:
+/// the requests are built by the crate, with their headers and whole body, e.g. JSON or multipart, and still go through the
+/// interceptors, retries and throttling.
+///
+/// Not available on `wasm32`, where the requests are always sent by the `fetch` API of the browser.
+pub trait HttpClient: Send + Sync {
+    /// Sends a request and returns its response
+    fn execute(&self, request: http::Request<Bytes>) -> ResponseFuture<'_>;
+}
+
+impl HttpClient for reqwest::Client {
+    fn execute(&self, request: http::Request<Bytes>) -> ResponseFuture<'_> {
+        Box::pin(async move {
+            let resp = reqwest::Client::execute(self, request.try_into()?).await?;
+            let (status, version, headers) =
+                (resp.status(), resp.version(), resp.headers().clone());
+            let body: ResponseBody = Box::pin(resp.bytes_stream().map(|chunk| Ok(chunk?)));
+            let mut response = http::Response::new(body);
+            *response.status_mut() = status;
+            *response.version_mut() = version;
+            *response.headers_mut() = headers;
+            Ok(response)
+        })
+    }
+}
+
+/// The transport of a client, compared by identity
+#[derive(Clone)]
+pub(crate) struct Transport(pub(crate) Arc<dyn HttpClient>);
+
+impl Transport {
+    /// Sends a request built by `reqwest` through the transport, adapting it and its response to the `http` types
+    pub(crate) async fn execute(
+        &self,
+        request: reqwest::Request,
+    ) -> crate::Result<reqwest::Response> {
+        let request: http::Request<reqwest::Body> = request.try_into()?;
+        let (parts, body) = request.into_parts();
+        let body = match body.as_bytes() {
+            Some(body) => Bytes::copy_from_slice(body),
+            // Streamed bodies, e.g. multipart forms, can only be read through a response
+            None => {
+                reqwest::Response::from(http::Response::new(body))
+                    .bytes()
+                    .await?
+            }
+        };
+        let response = self
+            .0
+            .execute(http::Request::from_parts(parts, body))
+            .await?;
+        Ok(response.map(reqwest::Body::wrap_stream).into())
+    }
+}
+
+impl fmt::Debug for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("HttpClient")
+    }
+}
+
+impl PartialEq for Transport {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl PartialOrd for Transport {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (self == other).then_some(Ordering::Equal)
+    }
+}
@@ -1,4 +1,6 @@
 use core::f32;
+use std::borrow::Cow;
+use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -26,77 +28,1011 @@ pub struct ConversationResponse {
     //   }
     // }
     /// Unique ID of the message
+    #[serde(default)]
     pub id: String,
     /// Content of this message
-    pub object: String,
+    #[serde(default)]
+    pub object: ObjectKind,
     /// Kind of sender. Either AI or user
+    #[serde(default)]
     pub created: u64,
     /// The user that sent this message
     pub choices: Vec<ConversationChoice>,
+    /// The number of tokens used by the request, zeroed when the backend doesn't return it
+    #[serde(default)]
     pub usage: Usage,
+    /// The service tier used to process the request, if returned by the API
+    pub service_tier: Option<ServiceTier>,
+    /// The upstream provider that served the request, returned by OpenRouter
+    #[serde(default)]
+    pub provider: Option<String>,
 }
 
-/// The message that the user or the AI sent
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd)]
+/// The number of tokens used by a request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd, Default)]
 pub struct Usage {
+    /// Number of tokens in the prompt
+    #[serde(default)]
     pub prompt_tokens: usize,
+    /// Number of tokens in the generated completion, always 0 for embeddings
+    #[serde(default)]
     pub completion_tokens: usize,
+    /// Total number of tokens used by the request
+    #[serde(default)]
     pub total_tokens: usize,
+    /// Breakdown of the tokens in the prompt, if returned by the API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+    /// Breakdown of the tokens in the completion, if returned by the API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_tokens_details: Option<CompletionTokensDetails>,
+    /// Whether the numbers were estimated with the local tokenizer rather than returned by the API
+    #[serde(skip)]
+    pub estimated: bool,
+}
+
+impl Usage {
+    /// Totals the tokens of many requests, e.g. for a dashboard. See the [`Add`](std::ops::Add) and [`Sum`](std::iter::Sum) implementations
+    pub fn sum<'a, I: IntoIterator<Item = &'a Usage>>(usages: I) -> Usage {
+        usages
+            .into_iter()
+            .fold(Usage::default(), |mut total, usage| {
+                total += usage;
+                total
+            })
+    }
+}
+
+/// Adds the tokens of another request, including the breakdowns, e.g. to keep a running total.
+///
+/// A breakdown field is only set in the total if it is set in any of the added usages, and the total is estimated if any of them is.
+impl std::ops::AddAssign<&Usage> for Usage {
+    fn add_assign(&mut self, other: &Usage) {
+        fn add(total: &mut Option<usize>, tokens: Option<usize>) {
+            if let Some(tokens) = tokens {
+                *total.get_or_insert(0) += tokens;
+            }
+        }
+
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+        if let Some(other) = &other.prompt_tokens_details {
+            let details = self
+                .prompt_tokens_details
+                .get_or_insert_with(Default::default);
+            add(&mut details.cached_tokens, other.cached_tokens);
+            add(&mut details.audio_tokens, other.audio_tokens);
+        }
+        if let Some(other) = &other.completion_tokens_details {
+            let details = self
+                .completion_tokens_details
+                .get_or_insert_with(Default::default);
+            add(&mut details.reasoning_tokens, other.reasoning_tokens);
+            add(&mut details.audio_tokens, other.audio_tokens);
+            add(
+                &mut details.accepted_prediction_tokens,
+                other.accepted_prediction_tokens,
+            );
+            add(
+                &mut details.rejected_prediction_tokens,
+                other.rejected_prediction_tokens,
+            );
+        }
+        self.estimated |= other.estimated;
+    }
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        *self += &other;
+    }
+}
+
+impl std::ops::Add for Usage {
+    type Output = Usage;
+
+    fn add(mut self, other: Usage) -> Usage {
+        self += &other;
+        self
+    }
+}
+
+impl<'a> std::iter::Sum<&'a Usage> for Usage {
+    fn sum<I: Iterator<Item = &'a Usage>>(usages: I) -> Usage {
+        Usage::sum(usages)
+    }
+}
+
+impl std::iter::Sum for Usage {
+    fn sum<I: Iterator<Item = Usage>>(usages: I) -> Usage {
+        usages.fold(Usage::default(), std::ops::Add::add)
+    }
+}
+
+/// Breakdown of the tokens in the prompt
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd, Default)]
+pub struct PromptTokensDetails {
+    /// Number of prompt tokens that were read from the prompt cache
+    pub cached_tokens: Option<usize>,
+    /// Number of audio tokens in the prompt
+    pub audio_tokens: Option<usize>,
+}
+
+/// Breakdown of the tokens in the completion
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd, Default)]
+pub struct CompletionTokensDetails {
+    /// Number of tokens generated by a reasoning model for reasoning, which are not part of the content
+    pub reasoning_tokens: Option<usize>,
+    /// Number of audio tokens generated by the model
+    pub audio_tokens: Option<usize>,
+    /// Number of predicted output tokens that appeared in the completion
+    pub accepted_prediction_tokens: Option<usize>,
+    /// Number of predicted output tokens that did not appear in the completion, which are still billed
+    pub rejected_prediction_tokens: Option<usize>,
+}
+
+/// A response that is received on the legacy text completions endpoint
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct TextCompletionResponse {
+    /// Unique ID of the completion
+    pub id: String,
+    /// Kind of object, [`ObjectKind::TextCompletion`]
+    pub object: ObjectKind,
+    /// Unix timestamp of the creation of the completion
+    pub created: u64,
+    /// The model used for the completion
+    pub model: String,
+    /// The generated completions
+    pub choices: Vec<TextCompletionChoice>,
+    /// The number of tokens used by the request
+    pub usage: Option<Usage>,
+}
+
+/// A completion generated by the legacy text completions endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TextCompletionChoice {
+    /// The generated text
+    pub text: String,
+    /// Index of the choice
+    pub index: u64,
+    /// Why the model stopped generating tokens
+    pub finish_reason: Option<String>,
+}
+
+/// A response that is received on the embeddings endpoint
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct EmbeddingResponse {
+    /// Kind of object, [`ObjectKind::List`]
+    pub object: ObjectKind,
+    /// The embeddings, one per input
+    pub data: Vec<Embedding>,
+    /// The model used to create the embeddings
+    pub model: String,
+    /// The number of tokens used by the request
+    pub usage: Usage,
+}
+
+/// The embedding of a single input
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Embedding {
+    /// Index of the input this is the embedding of
+    pub index: usize,
+    /// The embedding vector, decoded from base64 if it was requested in [`EncodingFormat::Base64`]
+    #[serde(deserialize_with = "deserialize_embedding")]
+    pub embedding: Vec<f32>,
+}
+
+/// Deserializes an embedding from either an array of floats or a base64 string of little-endian `f32`s
+fn deserialize_embedding<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<f32>, D::Error> {
+    use base64::Engine;
+    use serde::de::Error;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Encoded {
+        Float(Vec<f32>),
+        Base64(String),
+    }
+
+    match Encoded::deserialize(deserializer)? {
+        Encoded::Float(embedding) => Ok(embedding),
+        Encoded::Base64(encoded) => {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .map_err(D::Error::custom)?;
+            if bytes.len() % 4 != 0 {
+                return Err(D::Error::custom(format!(
+                    "base64 embedding of {} bytes is not an array of f32",
+                    bytes.len()
+                )));
+            }
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .collect())
+        }
+    }
 }
 
 /// The message that the user or the AI sent
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ConversationChoice {
+    #[serde(default)]
     pub index: u64,
     pub message: Message,
+    /// Why the model stopped generating this choice
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<FinishReason>,
+    /// What triggered a [`FinishReason::Stop`], returned by some compatible servers, e.g. vLLM, but not by OpenAI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<StopReason>,
+    /// The log probabilities of the generated tokens, returned when [`CompletionOptions::logprobs`] is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Logprobs>,
+    /// The results of the content filters on the generated content, returned by Azure OpenAI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_filter_results: Option<ContentFilterResults>,
 }
 
+impl ConversationChoice {
+    /// Whether content was omitted by the content filters, either from the [`FinishReason::ContentFilter`] or from the [`ContentFilterResults`]
+    pub fn is_filtered(&self) -> bool {
+        self.finish_reason == Some(FinishReason::ContentFilter)
+            || self
+                .content_filter_results
+                .as_ref()
+                .is_some_and(ContentFilterResults::is_filtered)
+    }
+
+    /// Returns the stop sequence that ended this choice, which is not part of its content.
+    ///
+    /// OpenAI returns the same [`FinishReason::Stop`] for a natural end and a stop sequence, so this is only known
+    /// when the backend returns a [`StopReason`].
+    pub fn stop_sequence(&self) -> Option<&str> {
+        match &self.stop_reason {
+            Some(StopReason::Sequence(sequence)) => Some(sequence),
+            _ => None,
+        }
+    }
+}
+
+/// The log probabilities of the tokens of a choice
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct Logprobs {
+    /// The log probabilities of the content tokens
+    #[serde(default)]
+    pub content: Option<Vec<TokenLogprob>>,
+    /// The log probabilities of the refusal tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<Vec<TokenLogprob>>,
+}
+
+impl Logprobs {
+    /// Returns the sum and mean of the log probabilities of the content tokens, and the perplexity of the content.
+    ///
+    /// A lower perplexity means the model was more confident, which can be used to rank candidate completions, e.g. the
+    /// choices of a request with [`CompletionOptions::n`]. Returns `None` if there are no content tokens.
+    pub fn stats(&self) -> Option<LogprobStats> {
+        let tokens = self.content.as_deref().unwrap_or_default();
+        if tokens.is_empty() {
+            return None;
+        }
+        let sum: f64 = tokens.iter().map(|token| token.logprob).sum();
+        let mean = sum / tokens.len() as f64;
+        Some(LogprobStats {
+            tokens: tokens.len(),
+            sum,
+            mean,
+            perplexity: (-mean).exp(),
+        })
+    }
+
+    /// Appends the tokens of the next streamed chunk
+    pub(crate) fn append(&mut self, other: Logprobs) {
+        for (tokens, other) in [
+            (&mut self.content, other.content),
+            (&mut self.refusal, other.refusal),
+        ] {
+            if let Some(other) = other {
+                tokens.get_or_insert_with(Vec::new).extend(other);
+            }
+        }
+    }
+}
+
+/// Summary statistics of the log probabilities of a completion, see [`Logprobs::stats`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogprobStats {
+    /// The number of tokens
+    pub tokens: usize,
+    /// The sum of the log probabilities, i.e. the log probability of the whole completion
+    pub sum: f64,
+    /// The mean log probability per token
+    pub mean: f64,
+    /// The perplexity, `exp(-mean)`, from 1 for a fully confident model upwards
+    pub perplexity: f64,
+}
+
+/// The log probability of a generated token
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TokenLogprob {
+    /// The token
+    pub token: String,
+    /// The log probability of the token
+    pub logprob: f64,
+    /// The UTF-8 bytes of the token, as tokens may split multi-byte characters
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
+    /// The most likely tokens at this position, up to [`CompletionOptions::top_logprobs`]
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+/// One of the most likely tokens at a position
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TopLogprob {
+    /// The token
+    pub token: String,
+    /// The log probability of the token
+    pub logprob: f64,
+    /// The UTF-8 bytes of the token
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
+}
+
+/// The results of the content filters, per category
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ContentFilterResults {
+    /// Hateful content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hate: Option<ContentFilterResult>,
+    /// Content related to self-harm
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub self_harm: Option<ContentFilterResult>,
+    /// Sexual content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sexual: Option<ContentFilterResult>,
+    /// Violent content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub violence: Option<ContentFilterResult>,
+    /// Profane content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profanity: Option<ContentFilterResult>,
+    /// Attempts to make the model bypass its rules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jailbreak: Option<ContentFilterResult>,
+    /// Known text, e.g. song lyrics or articles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protected_material_text: Option<ContentFilterResult>,
+    /// Known source code from public repositories
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protected_material_code: Option<ContentFilterResult>,
+}
+
+impl ContentFilterResults {
+    /// Whether any category made the content filters omit content
+    pub fn is_filtered(&self) -> bool {
+        [
+            &self.hate,
+            &self.self_harm,
+            &self.sexual,
+            &self.violence,
+            &self.profanity,
+            &self.jailbreak,
+            &self.protected_material_text,
+            &self.protected_material_code,
+        ]
+        .into_iter()
+        .flatten()
+        .any(|result| result.filtered)
+    }
+}
+
+/// The result of a content filter category
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ContentFilterResult {
+    /// Whether the content was omitted because of this category
+    #[serde(default)]
+    pub filtered: bool,
+    /// How severe the content is, for the categories graded by severity, e.g. hate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<ContentFilterSeverity>,
+    /// Whether the content was detected, for the categories that are only detected, e.g. jailbreak
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detected: Option<bool>,
+}
+
+/// How severe content is, according to the content filters
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentFilterSeverity {
+    /// Harmless content
+    Safe,
+    /// Content of low severity
+    Low,
+    /// Content of medium severity
+    Medium,
+    /// Content of high severity
+    High,
+    /// A severity unknown to this version of the crate
+    #[serde(other)]
+    Unknown,
+}
+
+/// What triggered a [`FinishReason::Stop`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum StopReason {
+    /// One of the stop sequences of the request
+    Sequence(String),
+    /// A stop token, by its ID
+    Token(u32),
+}
+
+/// The reason the model stopped generating a choice
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// The model finished its answer or hit a stop sequence
+    Stop,
+    /// The answer was truncated by `max_tokens`, `max_completion_tokens` or the context window of the model
+    Length,
+    /// The model called tools
+    ToolCalls,
+    /// Content was omitted by the content filters
+    ContentFilter,
+    /// The model called a function, with the deprecated `functions` parameter
+    FunctionCall,
+    /// A reason unknown to this version of the crate
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 pub struct Message {
+    /// The content of the message, either text or, e.g. for images and audio, an array of parts
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub content: Option<String>,
+    pub content: Option<Content>,
     pub role: String,
+    /// The function call requested by the model, on assistant messages of the legacy function calling
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<FunctionCall>,
+    /// The tool calls requested by the model, on assistant messages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// ID of the tool call this message is the result of, on tool messages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Name of the function this message is the result of, on function messages, or of the author of the message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The annotations of the content, e.g. the URLs cited by a search model. They are not sent back to the API
+    #[serde(default, skip_serializing)]
+    pub annotations: Vec<Annotation>,
+    /// Why the model declined to answer, on assistant messages, in which case the content is usually `None`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>,
+    /// The audio generated by the model when the request has the [`Modality::Audio`] modality, on assistant messages
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub function_call: Option<Value>,
+    pub audio: Option<AudioResponse>,
 }
 
-/// Kind of sender
+/// Audio generated by the model
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct AudioResponse {
+    /// ID of the audio, which is the only field sent back to the API to refer to it in the next turns
+    pub id: String,
+    /// Unix timestamp after which the audio can no longer be referred to
+    #[serde(default, skip_serializing)]
+    pub expires_at: u64,
+    /// The audio, base64-encoded in the format of [`AudioConfig::format`]
+    #[serde(default, skip_serializing)]
+    pub data: String,
+    /// The transcript of the audio
+    #[serde(default, skip_serializing)]
+    pub transcript: String,
+}
+
+/// An annotation of the content of a message
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Annotation {
+    /// A web page cited by the content
+    UrlCitation {
+        /// The citation
+        url_citation: UrlCitation,
+    },
+    /// An annotation of a type unknown to this version of the crate
+    #[serde(other)]
+    Unknown,
+}
+
+/// A web page cited by the content of a message
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UrlCitation {
+    /// Index of the first character of the citation in the content
+    pub start_index: usize,
+    /// Index after the last character of the citation in the content
+    pub end_index: usize,
+    /// URL of the web page
+    pub url: String,
+    /// Title of the web page
+    pub title: String,
+}
+
+/// A tool the model may call
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Tool {
+    /// Type of the tool. Currently, only `function` is supported
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The function the model may call
+    pub function: FunctionDefinition,
+}
+
+impl Tool {
+    /// Constructs a function tool, described by a JSON schema of its parameters
+    pub fn function<S: Into<String>>(name: S, description: S, parameters: Value) -> Self {
+        Self {
+            kind: "function".to_owned(),
+            function: FunctionDefinition {
+                name: name.into(),
+                description: Some(description.into()),
+                parameters: Some(parameters),
+            },
+        }
+    }
+
+    /// Constructs a function tool whose parameters are described by the JSON schema derived from `T`,
+    /// which is then the type to deserialize the [`ToolCallFunction::arguments`] into
+    #[cfg(feature = "schemars")]
+    pub fn from_schema<T: schemars::JsonSchema, S: Into<String>>(name: S, description: S) -> Self {
+        let mut parameters = schemars::schema_for!(T).to_value();
+        if let Some(schema) = parameters.as_object_mut() {
+            // The meta-schema and the name of the Rust type are noise in the tool definition
+            schema.remove("$schema");
+            schema.remove("title");
+        }
+        Self::function(name, description, parameters)
+    }
+}
+
+/// Definition of a function the model may call
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FunctionDefinition {
+    /// Name of the function
+    pub name: String,
+    /// What the function does, used by the model to choose when and how to call it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The parameters the function accepts, described as a JSON schema object
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Value>,
+}
+
+/// A call of a tool requested by the model
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ToolCall {
+    /// ID of the tool call, referenced by the message holding its result
+    pub id: String,
+    /// Type of the tool. Currently, only `function` is supported
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The function the model called
+    pub function: ToolCallFunction,
+}
+
+/// The function called in a [`ToolCall`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ToolCallFunction {
+    /// Name of the function
+    pub name: String,
+    /// Arguments of the call, as generated by the model in JSON format. They may not be valid JSON
+    pub arguments: String,
+}
+
+/// Kind of sender
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum Role {
+    /// Instructions given to the model, e.g. a system prompt
+    System,
+    /// Instructions given to the model, which reasoning models follow in place of system messages
+    Developer,
     /// A user sent this message
     User,
     /// An AI sent this message
     Assistant,
+    /// The result of a tool call, answering the call with [`Message::tool_call_id`]
+    Tool,
+    /// The result of a legacy function call
+    Function,
+}
+
+impl Role {
+    /// Returns the name of the role, as expected by the API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::Developer => "developer",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+            Role::Function => "function",
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = crate::err::Error;
+
+    fn from_str(role: &str) -> crate::Result<Self> {
+        match role {
+            "system" => Ok(Role::System),
+            "developer" => Ok(Role::Developer),
+            "user" => Ok(Role::User),
+            "assistant" => Ok(Role::Assistant),
+            "tool" => Ok(Role::Tool),
+            "function" => Ok(Role::Function),
+            role => Err(crate::err::Error::ParsingError(format!(
+                "unknown role {role:?}"
+            ))),
+        }
+    }
+}
+
+impl Message {
+    /// Constructs a user message made of text and a local image file, see [`ContentPart::image_file`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn user_with_image<S: Into<String>, P: AsRef<std::path::Path>>(
+        text: S,
+        path: P,
+    ) -> crate::Result<Self> {
+        Ok(Message {
+            role: Role::User.as_str().to_owned(),
+            content: Some(Content::Parts(vec![
+                ContentPart::text(text),
+                ContentPart::image_file(path).await?,
+            ])),
+            ..Default::default()
+        })
+    }
+
+    /// Constructs the message holding the result of a tool call, to send after the assistant message requesting it
+    pub fn tool_result<I: Into<String>, S: Into<String>>(tool_call_id: I, content: S) -> Self {
+        Message {
+            role: Role::Tool.as_str().to_owned(),
+            content: Some(Content::Text(content.into())),
+            tool_call_id: Some(tool_call_id.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Constructs the message holding the result of a function call requested with the deprecated `functions` parameter
+    pub fn function_result<N: Into<String>, S: Into<String>>(name: N, content: S) -> Self {
+        Message {
+            role: Role::Function.as_str().to_owned(),
+            content: Some(Content::Text(content.into())),
+            name: Some(name.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the role of the message, or `None` if it is not one of the known [`Role`]s
+    pub fn role(&self) -> Option<Role> {
+        self.role.parse().ok()
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::fmt::Display for Message {
+    /// Renders the message as `role: content`, see [`Content`]. Messages without content show the functions they call, if any
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:", self.role)?;
+        match (&self.content, &self.tool_calls) {
+            (Some(content), _) => write!(f, " {content}"),
+            (None, Some(calls)) if !calls.is_empty() => {
+                let names: Vec<&str> = calls
+                    .iter()
+                    .map(|call| call.function.name.as_str())
+                    .collect();
+                write!(f, " [calls {}]", names.join(", "))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl From<&str> for Message {
+    /// Constructs a user message
+    fn from(content: &str) -> Self {
+        Message::from((Role::User, content))
+    }
+}
+
+impl From<String> for Message {
+    /// Constructs a user message
+    fn from(content: String) -> Self {
+        Message::from((Role::User, content))
+    }
+}
+
+impl From<(Role, &str)> for Message {
+    fn from((role, content): (Role, &str)) -> Self {
+        Message::from((role, content.to_owned()))
+    }
+}
+
+impl From<(Role, String)> for Message {
+    fn from((role, content): (Role, String)) -> Self {
+        Message {
+            role: role.as_str().to_owned(),
+            content: Some(Content::Text(content)),
+            ..Default::default()
+        }
+    }
+}
+
+/// Checks that the messages form a conversation the API accepts, describing the first problem found.
+///
+/// The list must not be empty, every message must have a known role and the content its role requires,
+/// and the tool calls of an assistant message must be answered by the tool messages right after it, one per call.
+pub fn validate_messages(messages: &[Message]) -> crate::Result<()> {
+    let invalid = |index: usize, reason: String| {
+        Err(crate::err::Error::InvalidRequest(format!(
+            "message {index}: {reason}"
+        )))
+    };
+    if messages.is_empty() {
+        return Err(crate::err::Error::InvalidRequest(
+            "at least one message must be sent".to_owned(),
+        ));
+    }
+    let has_content = |message: &Message| {
+        message
+            .content
+            .as_ref()
+            .is_some_and(|content| !content.is_empty())
+    };
+    // IDs of the tool calls of the latest assistant message that are yet to be answered
+    let mut pending: Vec<&str> = Vec::new();
+    for (index, message) in messages.iter().enumerate() {
+        if message.role() != Some(Role::Tool) && !pending.is_empty() {
+            return invalid(
+                index,
+                format!("tool calls {pending:?} must be answered by tool messages first"),
+            );
+        }
+        match message.role() {
+            Some(Role::System | Role::Developer | Role::User) if !has_content(message) => {
+                return invalid(
+                    index,
+                    format!("{} messages must have content", message.role),
+                );
+            }
+            Some(Role::System | Role::Developer | Role::User | Role::Function) => {}
+            Some(Role::Assistant) => {
+                let calls = message.tool_calls.iter().flatten();
+                pending = calls.map(|call| call.id.as_str()).collect();
+                if !has_content(message)
+                    && pending.is_empty()
+                    && message.function_call.is_none()
+                    && message.audio.is_none()
+                    && message.refusal.is_none()
+                {
+                    return invalid(
+                        index,
+                        "assistant messages must have content or tool calls".to_owned(),
+                    );
+                }
+            }
+            Some(Role::Tool) => {
+                let Some(id) = message.tool_call_id.as_deref() else {
+                    return invalid(index, "tool messages must have a tool_call_id".to_owned());
+                };
+                let Some(position) = pending.iter().position(|pending| *pending == id) else {
+                    return invalid(
+                        index,
+                        format!("tool message answers {id:?}, which the preceding assistant message didn't call"),
+                    );
+                };
+                pending.remove(position);
+            }
+            None => return invalid(index, format!("unknown role {:?}", message.role)),
+        }
+    }
+    if !pending.is_empty() {
+        return invalid(
+            messages.len(),
+            format!("tool calls {pending:?} must be answered by tool messages"),
+        );
+    }
+    Ok(())
+}
+
+/// Values that can be sent as the messages of a chat completion: a single message, or a list of them.
+///
+/// A single message can be a [`Message`], a `&str` or `String` sent by the user, or a `(Role, content)` tuple.
+pub trait IntoMessages {
+    /// Converts the value into a list of messages
+    fn into_messages(self) -> Vec<Message>;
+}
+
+impl IntoMessages for Message {
+    fn into_messages(self) -> Vec<Message> {
+        vec![self]
+    }
+}
+
+impl IntoMessages for &str {
+    fn into_messages(self) -> Vec<Message> {
+        vec![self.into()]
+    }
+}
+
+impl IntoMessages for String {
+    fn into_messages(self) -> Vec<Message> {
+        vec![self.into()]
+    }
+}
+
+impl IntoMessages for (Role, &str) {
+    fn into_messages(self) -> Vec<Message> {
+        vec![self.into()]
+    }
+}
+
+impl IntoMessages for (Role, String) {
+    fn into_messages(self) -> Vec<Message> {
+        vec![self.into()]
+    }
+}
+
+impl<M: Into<Message>> IntoMessages for Vec<M> {
+    fn into_messages(self) -> Vec<Message> {
+        self.into_iter().map(Into::into).collect()
+    }
+}
+
+impl<M: Into<Message>, const N: usize> IntoMessages for [M; N] {
+    fn into_messages(self) -> Vec<Message> {
+        self.into_iter().map(Into::into).collect()
+    }
+}
+
+impl IntoMessages for &[Message] {
+    fn into_messages(self) -> Vec<Message> {
+        self.to_vec()
+    }
+}
+
+/// The error object returned by the API when a request fails
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ApiErrorBody {
+    /// Human-readable description of the error
+    pub message: String,
+    /// Kind of the error, e.g. `invalid_request_error`
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// The request parameter the error is about, e.g. `messages`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub param: Option<String>,
+    /// Machine-readable code of the error, e.g. `context_length_exceeded`. Numeric codes of compatible servers are converted to strings
+    #[serde(
+        default,
+        deserialize_with = "deserialize_error_code",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub code: Option<String>,
+}
+
+/// Deserializes an error code from either a string or a number
+fn deserialize_error_code<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    Ok(match Option::<Value>::deserialize(deserializer)? {
+        Some(Value::String(code)) => Some(code),
+        Some(Value::Number(code)) => Some(code.to_string()),
+        _ => None,
+    })
+}
+
+/// A page of a list returned by the API, e.g. the uploaded files
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct List<T> {
+    pub data: Vec<T>,
+}
+
+/// The envelope wrapping an [`ApiErrorBody`] in error responses
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub(crate) struct ApiErrorEnvelope {
+    pub error: ApiErrorBody,
 }
 
 /// Part of a mapped response returned from the [`ChatGPT::send_message_streaming()`](`chatgpt::client::ChatGPT::send_message_streaming()`) method
+#[allow(clippy::large_enum_variant)] // chunks are streamed by value, boxing them would only add an allocation per chunk
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum ResponsePart {
     /// Got a chunk of response containing unfinished message response
     Chunk(ChatCompletionChunk),
     /// Got an indication that the final response was returned
     Done,
+    /// Got an error event from the API, e.g. when the server failed while generating the response.
+    ///
+    /// It is surfaced as a value rather than an `Err`, which is kept for transport and parsing failures, so that callers can
+    /// tell them apart and decide whether to keep reading the stream.
+    Error(ApiErrorBody),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ChatCompletionChunk {
-    id: String,
-    created: u64,
+    /// Kind of object, [`ObjectKind::ChatCompletionChunk`]
+    #[serde(default = "ObjectKind::chunk")]
+    pub object: ObjectKind,
+    pub(crate) id: String,
+    #[serde(default)]
+    pub(crate) created: u64,
+    #[serde(default)]
     model: String,
     pub choices: Vec<ChoiceChunk>,
+    /// The upstream provider that served the request, returned by OpenRouter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// The number of tokens used by the request, only sent in the last chunk when [`StreamOptions::include_usage`] is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ChoiceChunk {
     pub delta: DeltaChunk,
-    index: usize,
+    pub(crate) index: usize,
+    /// Why the model stopped generating this choice, only sent in its last chunk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<FinishReason>,
+    /// What triggered a [`FinishReason::Stop`], only sent in the last chunk by the backends returning it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<StopReason>,
+    /// The log probabilities of the tokens of this chunk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Logprobs>,
+    /// The results of the content filters on the content generated so far, returned by Azure OpenAI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_filter_results: Option<ContentFilterResults>,
 }
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct DeltaChunk {
     pub function_call: Option<FunctionCallDelta>,
     pub content: Option<String>,
-    role: Option<String>,
+    pub(crate) role: Option<String>,
+    /// A fragment of the reason the model declined to answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>,
+    /// Fragments of the tool calls requested by the model, see [`ToolCallAssembler`](crate::stream::ToolCallAssembler)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+/// A fragment of a tool call, received while streaming
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ToolCallDelta {
+    /// Position of the tool call among the tool calls of the response
+    pub index: usize,
+    /// ID of the tool call, sent in its first fragment
+    pub id: Option<String>,
+    /// Type of the tool, sent in its first fragment
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// Fragment of the called function
+    pub function: Option<FunctionCallDelta>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -105,9 +1041,642 @@ pub struct FunctionCallDelta {
     pub arguments: Option<String>,
 }
 
+/// A call of a legacy function: the function forced with [`CompletionOptions::function_call`], or the call requested by the model in [`Message::function_call`]
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 pub struct FunctionCall {
+    /// Name of the function
     pub name: String,
+    /// Arguments of the call, as generated by the model in JSON format. They are empty when forcing a function, and may not be valid JSON
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub arguments: String,
+}
+
+impl FunctionCall {
+    /// Parses the arguments of the call, failing with [`Error::SerdeError`](crate::err::Error::SerdeError) if they are not valid JSON
+    pub fn parse_arguments(&self) -> crate::Result<Value> {
+        Ok(serde_json::from_str(&self.arguments)?)
+    }
+}
+
+/// The kind of object returned by the API, serialized as its `object` identifier
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
+#[serde(from = "String", into = "String")]
+pub enum ObjectKind {
+    /// `chat.completion`, the default when a compatible backend omits it
+    #[default]
+    ChatCompletion,
+    /// `chat.completion.chunk`
+    ChatCompletionChunk,
+    /// `text_completion`
+    TextCompletion,
+    /// `list`
+    List,
+    /// An object unknown to this version of the crate
+    Other(String),
+}
+
+impl ObjectKind {
+    /// Returns the identifier of the object kind
+    pub fn as_str(&self) -> &str {
+        match self {
+            ObjectKind::ChatCompletion => "chat.completion",
+            ObjectKind::ChatCompletionChunk => "chat.completion.chunk",
+            ObjectKind::TextCompletion => "text_completion",
+            ObjectKind::List => "list",
+            ObjectKind::Other(object) => object,
+        }
+    }
+
+    /// The object kind of chunks omitting it
+    fn chunk() -> Self {
+        ObjectKind::ChatCompletionChunk
+    }
+}
+
+impl From<String> for ObjectKind {
+    fn from(object: String) -> Self {
+        match object.as_str() {
+            "chat.completion" => ObjectKind::ChatCompletion,
+            "chat.completion.chunk" => ObjectKind::ChatCompletionChunk,
+            "text_completion" => ObjectKind::TextCompletion,
+            "list" => ObjectKind::List,
+            _ => ObjectKind::Other(object),
+        }
+    }
+}
+
+impl From<ObjectKind> for String {
+    fn from(object: ObjectKind) -> Self {
+        match object {
+            ObjectKind::Other(object) => object,
+            object => object.as_str().to_owned(),
+        }
+    }
+}
+
+/// A chat model, serialized as its canonical identifier
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(from = "String", into = "String")]
+pub enum Model {
+    /// `gpt-4o`
+    Gpt4o,
+    /// `gpt-4o-mini`
+    Gpt4oMini,
+    /// `gpt-4-turbo`
+    Gpt4Turbo,
+    /// `gpt-4`
+    Gpt4,
+    /// `gpt-3.5-turbo`
+    Gpt35Turbo,
+    /// `o1`
+    O1,
+    /// `o1-mini`
+    O1Mini,
+    /// `o3-mini`
+    O3Mini,
+    /// Any other model identifier, e.g. a fine-tuned model
+    Custom(String),
+}
+
+impl Model {
+    /// Returns the canonical identifier of the model, as expected by the API
+    pub fn id(&self) -> &str {
+        match self {
+            Model::Gpt4o => "gpt-4o",
+            Model::Gpt4oMini => "gpt-4o-mini",
+            Model::Gpt4Turbo => "gpt-4-turbo",
+            Model::Gpt4 => "gpt-4",
+            Model::Gpt35Turbo => "gpt-3.5-turbo",
+            Model::O1 => "o1",
+            Model::O1Mini => "o1-mini",
+            Model::O3Mini => "o3-mini",
+            Model::Custom(id) => id,
+        }
+    }
+
+    /// Returns the size of the context window of the model in tokens, if known
+    pub fn context_window(&self) -> Option<usize> {
+        match self {
+            Model::Gpt4o | Model::Gpt4oMini | Model::Gpt4Turbo | Model::O1Mini => Some(128_000),
+            Model::Gpt4 => Some(8_192),
+            Model::Gpt35Turbo => Some(16_385),
+            Model::O1 | Model::O3Mini => Some(200_000),
+            Model::Custom(_) => None,
+        }
+    }
+
+    /// Returns the maximum number of tokens the model can generate in a completion, if known
+    pub fn max_output_tokens(&self) -> Option<usize> {
+        match self {
+            Model::Gpt4o | Model::Gpt4oMini => Some(16_384),
+            Model::Gpt4Turbo | Model::Gpt35Turbo => Some(4_096),
+            Model::Gpt4 => Some(8_192),
+            Model::O1 | Model::O3Mini => Some(100_000),
+            Model::O1Mini => Some(65_536),
+            Model::Custom(_) => None,
+        }
+    }
+
+    /// Returns the role the model expects instructions in: [`Role::Developer`] for reasoning models, see [`Self::is_reasoning`],
+    /// and [`Role::System`] otherwise
+    pub fn instruction_role(&self) -> Role {
+        match self.is_reasoning() {
+            true => Role::Developer,
+            false => Role::System,
+        }
+    }
+
+    /// Whether the model is an o-series reasoning model, which takes `max_completion_tokens` instead of `max_tokens`
+    pub fn is_reasoning(&self) -> bool {
+        match self {
+            Model::O1 | Model::O1Mini | Model::O3Mini => true,
+            Model::Custom(id) => {
+                let mut chars = id.chars();
+                chars.next() == Some('o') && chars.next().is_some_and(|c| c.is_ascii_digit())
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Model {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+impl From<&str> for Model {
+    fn from(id: &str) -> Self {
+        match id {
+            "gpt-4o" => Model::Gpt4o,
+            "gpt-4o-mini" => Model::Gpt4oMini,
+            "gpt-4-turbo" => Model::Gpt4Turbo,
+            "gpt-4" => Model::Gpt4,
+            "gpt-3.5-turbo" => Model::Gpt35Turbo,
+            "o1" => Model::O1,
+            "o1-mini" => Model::O1Mini,
+            "o3-mini" => Model::O3Mini,
+            id => Model::Custom(id.to_owned()),
+        }
+    }
+}
+
+impl From<String> for Model {
+    fn from(id: String) -> Self {
+        match Model::from(id.as_str()) {
+            Model::Custom(_) => Model::Custom(id),
+            model => model,
+        }
+    }
+}
+
+impl From<Model> for String {
+    fn from(model: Model) -> Self {
+        match model {
+            Model::Custom(id) => id,
+            model => model.id().to_owned(),
+        }
+    }
+}
+
+/// The format the model must output
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// Plain text
+    Text,
+    /// Any valid JSON object. The messages must also instruct the model to produce JSON
+    JsonObject,
+    /// JSON matching the provided schema
+    JsonSchema {
+        /// The schema the output must match
+        json_schema: JsonSchema,
+    },
+}
+
+/// A JSON schema the output of the model must match
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JsonSchema {
+    /// Name of the response format
+    pub name: String,
+    /// What the response format is for, used by the model to determine how to respond
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The schema, described as a JSON schema object
+    pub schema: Value,
+    /// Whether to enable strict schema adherence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+/// The latency tier used to process a request
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceTier {
+    /// Uses the tier configured for the project
+    Auto,
+    /// Standard pricing and performance
+    Default,
+    /// Lower prices for slower responses and occasional resource unavailability
+    Flex,
+    /// Faster processing at a higher price
+    Priority,
+    /// Processed with the scale tier credits of the project
+    Scale,
+    /// A tier unknown to this version of the crate, only found in responses
+    #[serde(other)]
+    Unknown,
+}
+
+/// Content made of either plain text or an array of parts
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Content {
+    /// Plain text
+    Text(String),
+    /// An array of content parts
+    Parts(Vec<ContentPart>),
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Content::Text(String::new())
+    }
+}
+
+impl Content {
+    /// Whether there is no text, or no parts
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Content::Text(text) => text.is_empty(),
+            Content::Parts(parts) => parts.is_empty(),
+        }
+    }
+
+    /// Returns the text, which is the concatenation of the text parts of an array
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            Content::Text(text) => Cow::Borrowed(text),
+            Content::Parts(parts) => Cow::Owned(
+                parts
+                    .iter()
+                    .filter_map(|part| match part {
+                        ContentPart::Text { text } => Some(text.as_str()),
+                        ContentPart::ImageUrl { .. } | ContentPart::InputAudio { .. } => None,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Like [`Self::text`], but consumes the content
+    pub fn into_text(self) -> String {
+        match self {
+            Content::Text(text) => text,
+            parts => parts.text().into_owned(),
+        }
+    }
+
+    /// Appends text, as a new part of an array
+    pub fn push_str(&mut self, text: &str) {
+        match self {
+            Content::Text(content) => content.push_str(text),
+            Content::Parts(parts) => parts.push(ContentPart::text(text)),
+        }
+    }
+}
+
+impl std::fmt::Display for Content {
+    /// Renders the text, with the text parts of an array separated by spaces and the other parts as `[image]` or `[audio]`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts = match self {
+            Content::Text(text) => return f.write_str(text),
+            Content::Parts(parts) => parts,
+        };
+        for (index, part) in parts.iter().enumerate() {
+            if index > 0 {
+                f.write_str(" ")?;
+            }
+            match part {
+                ContentPart::Text { text } => f.write_str(text)?,
+                ContentPart::ImageUrl { .. } => f.write_str("[image]")?,
+                ContentPart::InputAudio { .. } => f.write_str("[audio]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<&str> for Content {
+    fn from(text: &str) -> Self {
+        Content::Text(text.to_owned())
+    }
+}
+
+impl From<String> for Content {
+    fn from(text: String) -> Self {
+        Content::Text(text)
+    }
+}
+
+impl From<Vec<ContentPart>> for Content {
+    fn from(parts: Vec<ContentPart>) -> Self {
+        Content::Parts(parts)
+    }
+}
+
+/// A part of a [`Content`] array
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    /// A text part
+    Text {
+        /// The text
+        text: String,
+    },
+    /// An image part, only supported by vision models, e.g. `gpt-4o`
+    ImageUrl {
+        /// The image
+        image_url: ImageUrl,
+    },
+    /// An audio part, only supported by audio models, e.g. `gpt-4o-audio-preview`
+    InputAudio {
+        /// The audio
+        input_audio: InputAudio,
+    },
+}
+
+impl ContentPart {
+    /// Constructs a text part
+    pub fn text<S: Into<String>>(text: S) -> Self {
+        ContentPart::Text { text: text.into() }
+    }
+
+    /// Constructs an image part from the URL of an image, or a `data:` URL holding it
+    pub fn image_url<S: Into<String>>(url: S) -> Self {
+        ContentPart::ImageUrl {
+            image_url: ImageUrl {
+                url: url.into(),
+                detail: None,
+            },
+        }
+    }
+
+    /// Constructs an image part from the content of an image, embedded in a `data:` URL of the given MIME type, e.g. `image/png`
+    pub fn image_bytes<B: AsRef<[u8]>>(mime: &str, image: B) -> Self {
+        use base64::Engine;
+
+        let data = base64::engine::general_purpose::STANDARD.encode(image);
+        Self::image_url(format!("data:{mime};base64,{data}"))
+    }
+
+    /// Constructs an image part from a local PNG, JPEG, WebP or GIF file, embedded in a `data:` URL.
+    ///
+    /// The format is inferred from the extension of the file. Not available on `wasm32`, use [`ContentPart::image_bytes`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn image_file<P: AsRef<std::path::Path>>(path: P) -> crate::Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        let mime = match extension.as_deref() {
+            Some("png") => "image/png",
+            Some("jpg" | "jpeg") => "image/jpeg",
+            Some("webp") => "image/webp",
+            Some("gif") => "image/gif",
+            _ => {
+                return Err(crate::err::Error::InvalidRequest(format!(
+                    "unsupported image format of {}, expected png, jpeg, webp or gif",
+                    path.display()
+                )))
+            }
+        };
+        Ok(Self::image_bytes(mime, tokio::fs::read(path).await?))
+    }
+}
+
+/// An image sent to the model
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ImageUrl {
+    /// The URL of the image, or a `data:` URL holding it base64-encoded
+    pub url: String,
+    /// The resolution the model sees the image at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<ImageDetail>,
+}
+
+/// The resolution the model sees an image at, trading off understanding for tokens
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageDetail {
+    /// Lets the model choose depending on the size of the image
+    Auto,
+    /// A 512x512 version of the image, for a fixed budget of 85 tokens
+    Low,
+    /// The full image, split into tiles
+    High,
+}
+
+/// Audio sent to the model
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InputAudio {
+    /// The audio, base64-encoded
+    pub data: String,
+    /// The format of the audio
+    pub format: InputAudioFormat,
+}
+
+/// The format of audio sent to the model
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InputAudioFormat {
+    /// WAV
+    Wav,
+    /// MP3
+    Mp3,
+}
+
+/// A kind of output a model can generate
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Modality {
+    /// Text, returned in [`Message::content`]
+    Text,
+    /// Audio, returned in [`Message::audio`]. Requires [`CompletionOptions::audio`] to be set
+    Audio,
+}
+
+/// The voice and format of the audio generated by the model
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AudioConfig {
+    /// The voice the model speaks with
+    pub voice: Voice,
+    /// The format of the generated audio
+    pub format: AudioFormat,
+}
+
+/// A voice of the audio models
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Voice {
+    /// `alloy`
+    Alloy,
+    /// `ash`
+    Ash,
+    /// `ballad`
+    Ballad,
+    /// `coral`
+    Coral,
+    /// `echo`
+    Echo,
+    /// `fable`, only supported by text-to-speech
+    Fable,
+    /// `nova`, only supported by text-to-speech
+    Nova,
+    /// `onyx`, only supported by text-to-speech
+    Onyx,
+    /// `sage`
+    Sage,
+    /// `shimmer`
+    Shimmer,
+    /// `verse`
+    Verse,
+}
+
+/// The format of the audio generated by the model
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioFormat {
+    /// WAV
+    Wav,
+    /// MP3
+    Mp3,
+    /// FLAC
+    Flac,
+    /// Opus
+    Opus,
+    /// Raw 16-bit PCM at 24kHz, the only format supported when streaming
+    Pcm16,
+}
+
+/// Predicted output of a request, which speeds up responses that mostly match it, e.g. when editing a file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Prediction {
+    /// Content that is expected to be returned, mostly unchanged
+    Content {
+        /// The predicted content
+        content: Content,
+    },
+}
+
+impl Prediction {
+    /// Constructs a prediction of the content, from either a string or an array of text parts
+    pub fn content<C: Into<Content>>(content: C) -> Self {
+        Prediction::Content {
+            content: content.into(),
+        }
+    }
+}
+
+/// Options of a streamed response
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct StreamOptions {
+    /// Whether to send the usage of the request in an additional chunk, with no choices, right before `[DONE]`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_usage: Option<bool>,
+}
+
+/// Options of the web search performed by search models
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct WebSearchOptions {
+    /// How much context is retrieved from the web for the answer, defaults to medium
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_context_size: Option<SearchContextSize>,
+    /// Approximate location of the user, to refine the search results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_location: Option<UserLocation>,
+}
+
+/// How much context is retrieved from the web
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchContextSize {
+    /// Least context, lowest cost and latency
+    Low,
+    /// A balance between context, cost and latency
+    Medium,
+    /// Most comprehensive context, highest cost and latency
+    High,
+}
+
+/// Location of the user for the web search
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UserLocation {
+    /// An approximate location
+    Approximate {
+        /// The location
+        approximate: ApproximateLocation,
+    },
+}
+
+/// An approximate location, every field of which is optional
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct ApproximateLocation {
+    /// Free text name of the city, e.g. `San Francisco`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    /// Two-letter ISO code of the country, e.g. `US`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    /// Free text name of the region, e.g. `California`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    /// IANA timezone, e.g. `America/Los_Angeles`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+/// How much effort a reasoning model spends on reasoning before answering
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasoningEffort {
+    /// Favors speed and economical token usage
+    Low,
+    /// A balance between speed and reasoning accuracy
+    Medium,
+    /// Favors more complete reasoning
+    High,
+}
+
+/// A named sampling profile, applied with [`CompletionOptions::preset`]
+///
+/// | Preset | temperature | top_p | presence_penalty | frequency_penalty |
+/// |---|---|---|---|---|
+/// | `Precise` | 0.2 | 1.0 | 0.0 | 0.0 |
+/// | `Balanced` | 0.7 | 1.0 | 0.0 | 0.0 |
+/// | `Creative` | 1.1 | 1.0 | 0.6 | 0.3 |
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Preset {
+    /// Focused and mostly deterministic output, for extraction, classification and code
+    Precise,
+    /// The usual chat behavior, slightly less random than the API defaults
+    Balanced,
+    /// More varied output that avoids repeating itself, for brainstorming and fiction
+    Creative,
+}
+
+impl Preset {
+    /// Returns the temperature, top_p, presence_penalty and frequency_penalty of the preset
+    pub fn values(self) -> (f32, f32, f32, f32) {
+        match self {
+            Preset::Precise => (0.2, 1.0, 0.0, 0.0),
+            Preset::Balanced => (0.7, 1.0, 0.0, 0.0),
+            Preset::Creative => (1.1, 1.0, 0.6, 0.3),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
@@ -116,11 +1685,11 @@ pub struct CompletionOptions {
     ///string
     ///Required
     ///
-    ///ID of the model to use. Currently, only gpt-3.5-turbo and gpt-3.5-turbo-0301 are supported.
+    ///ID of the model to use. Either one of the known [`Model`]s or a [`Model::Custom`] identifier.
     ///messages
     ///array
     ///Required
-    pub model: Option<String>,
+    pub model: Option<Model>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub function_call: Option<FunctionCall>,
@@ -154,7 +1723,7 @@ pub struct CompletionOptions {
     ///Optional
     ///Defaults to 1
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub n: Option<f32>,
+    pub n: Option<usize>,
     ///
     ///How many chat completion choices to generate for each input message.
     ///stream
@@ -165,6 +1734,23 @@ pub struct CompletionOptions {
     ///If set, partial message deltas will be sent, like in ChatGPT. Tokens will be sent as data-only server-sent events as they become available, with the stream terminated by a data: [DONE] message.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    ///best_of
+    ///integer
+    ///Optional
+    ///Defaults to 1
+    ///
+    ///Generates best_of completions server-side and returns the n ones with the highest log probability per token.
+    ///Only supported by the legacy completions endpoint, see [`ChatGPT::complete`](crate::client::ChatGPT::complete).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best_of: Option<usize>,
+    ///stream_options
+    ///object or null
+    ///Optional
+    ///Defaults to null
+    ///
+    ///Options for streaming response. Only set this when you set stream: true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
     ///stop
     ///string or array
     ///Optional
@@ -179,8 +1765,26 @@ pub struct CompletionOptions {
     ///Defaults to inf
     ///
     ///The maximum number of tokens allowed for the generated answer. By default, the number of tokens the model can return will be (4096 - prompt tokens).
+    ///
+    ///Reasoning models (see [`Model::is_reasoning`]) reject this parameter, so when one of them is targeted
+    ///the client sends it as `max_completion_tokens` instead.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<usize>,
+    ///max_completion_tokens
+    ///integer
+    ///Optional
+    ///
+    ///An upper bound for the number of tokens that can be generated for a completion, including visible output tokens and reasoning tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<usize>,
+    ///reasoning_effort
+    ///string
+    ///Optional
+    ///Defaults to medium
+    ///
+    ///Constrains effort on reasoning for reasoning models. Reducing reasoning effort can result in faster responses and fewer tokens used on reasoning.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<ReasoningEffort>,
     ///presence_penalty
     ///number
     ///Optional
@@ -190,7 +1794,7 @@ pub struct CompletionOptions {
     ///
     ///See more information about frequency and presence penalties.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub presence_penalty: Option<usize>,
+    pub presence_penalty: Option<f32>,
     ///frequency_penalty
     ///number
     ///Optional
@@ -198,7 +1802,7 @@ pub struct CompletionOptions {
     ///
     ///Number between -2.0 and 2.0. Positive values penalize new tokens based on their existing frequency in the text so far, decreasing the model's likelihood to repeat the same line verbatim.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub frequency_penalty: Option<usize>,
+    pub frequency_penalty: Option<f32>,
     ///user
     ///string
     ///Optional
@@ -206,4 +1810,1328 @@ pub struct CompletionOptions {
     ///A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse. Learn more.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<isize>,
+    ///logit_bias
+    ///map
+    ///Optional
+    ///Defaults to null
+    ///
+    ///Modify the likelihood of specified tokens appearing in the completion. Maps token ids to a bias value from -100 to 100.
+    ///Values between -1 and 1 decrease or increase the likelihood of selection, while -100 or 100 ban or force the token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logit_bias: Option<HashMap<u32, f32>>,
+    ///logprobs
+    ///boolean
+    ///Optional
+    ///Defaults to false
+    ///
+    ///Whether to return the log probabilities of the output tokens in [`ConversationChoice::logprobs`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<bool>,
+    ///top_logprobs
+    ///integer
+    ///Optional
+    ///
+    ///An integer between 0 and 20 specifying the number of most likely tokens to return at each position, each with its log probability. logprobs must be set to true if this parameter is used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_logprobs: Option<u8>,
+    ///tools
+    ///array
+    ///Optional
+    ///
+    ///A list of tools the model may call. Currently, only functions are supported as a tool.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    ///parallel_tool_calls
+    ///boolean
+    ///Optional
+    ///Defaults to true
+    ///
+    ///Whether to enable parallel function calling during tool use. Set it to false to get at most one tool call per response.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parallel_tool_calls: Option<bool>,
+    ///service_tier
+    ///string
+    ///Optional
+    ///Defaults to auto
+    ///
+    ///Specifies the latency tier to use for processing the request. The tier actually used is returned in [`ConversationResponse::service_tier`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_tier: Option<ServiceTier>,
+    ///response_format
+    ///object
+    ///Optional
+    ///
+    ///An object specifying the format that the model must output, e.g. JSON matching a schema.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    ///web_search_options
+    ///object
+    ///Optional
+    ///
+    ///Lets search models, e.g. `gpt-4o-search-preview`, search the web for the answer. The cited pages are returned in [`Message::annotations`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub web_search_options: Option<WebSearchOptions>,
+    ///store
+    ///boolean or null
+    ///Optional
+    ///Defaults to false
+    ///
+    ///Whether to store the output of this request, e.g. to view it in the dashboard or use it for distillation and evals.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<bool>,
+    ///metadata
+    ///map
+    ///Optional
+    ///
+    ///Up to 16 key-value pairs to tag the request with, e.g. to filter stored completions in the dashboard.
+    ///Keys are at most 64 characters long and values at most 512 characters long, see [`Self::with_metadata`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+    ///prediction
+    ///object
+    ///Optional
+    ///
+    ///Predicted output, which greatly reduces the latency when large parts of the response are known ahead of time, e.g. when regenerating a file with minor changes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prediction: Option<Prediction>,
+    ///modalities
+    ///array or null
+    ///Optional
+    ///Defaults to ["text"]
+    ///
+    ///The kinds of output the model should generate. Audio models, e.g. `gpt-4o-audio-preview`, can also generate audio with `["text", "audio"]`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modalities: Option<Vec<Modality>>,
+    ///audio
+    ///object or null
+    ///Optional
+    ///
+    ///The voice and format of the generated audio. Required when [`Self::modalities`] contains [`Modality::Audio`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioConfig>,
+}
+
+impl CompletionOptions {
+    /// Overlays the options set in `overrides` onto these ones, e.g. to layer per-request options over shared defaults.
+    ///
+    /// Every field that is `Some` in `overrides` wins, and the others are kept. Collections such as `tools` or `metadata`
+    /// are replaced as a whole rather than merged.
+    pub fn merge(self, overrides: CompletionOptions) -> CompletionOptions {
+        let CompletionOptions {
+            model,
+            function_call,
+            temperature,
+            functions,
+            top_p,
+            n,
+            stream,
+            best_of,
+            stream_options,
+            stop,
+            max_tokens,
+            max_completion_tokens,
+            reasoning_effort,
+            presence_penalty,
+            frequency_penalty,
+            user,
+            logit_bias,
+            logprobs,
+            top_logprobs,
+            tools,
+            parallel_tool_calls,
+            service_tier,
+            response_format,
+            web_search_options,
+            store,
+            metadata,
+            prediction,
+            modalities,
+            audio,
+        } = overrides;
+        CompletionOptions {
+            model: model.or(self.model),
+            function_call: function_call.or(self.function_call),
+            temperature: temperature.or(self.temperature),
+            functions: functions.or(self.functions),
+            top_p: top_p.or(self.top_p),
+            n: n.or(self.n),
+            stream: stream.or(self.stream),
+            best_of: best_of.or(self.best_of),
+            stream_options: stream_options.or(self.stream_options),
+            stop: stop.or(self.stop),
+            max_tokens: max_tokens.or(self.max_tokens),
+            max_completion_tokens: max_completion_tokens.or(self.max_completion_tokens),
+            reasoning_effort: reasoning_effort.or(self.reasoning_effort),
+            presence_penalty: presence_penalty.or(self.presence_penalty),
+            frequency_penalty: frequency_penalty.or(self.frequency_penalty),
+            user: user.or(self.user),
+            logit_bias: logit_bias.or(self.logit_bias),
+            logprobs: logprobs.or(self.logprobs),
+            top_logprobs: top_logprobs.or(self.top_logprobs),
+            tools: tools.or(self.tools),
+            parallel_tool_calls: parallel_tool_calls.or(self.parallel_tool_calls),
+            service_tier: service_tier.or(self.service_tier),
+            response_format: response_format.or(self.response_format),
+            web_search_options: web_search_options.or(self.web_search_options),
+            store: store.or(self.store),
+            metadata: metadata.or(self.metadata),
+            prediction: prediction.or(self.prediction),
+            modalities: modalities.or(self.modalities),
+            audio: audio.or(self.audio),
+        }
+    }
+
+    /// Constructs options with the sampling parameters of a [`Preset`], which can be further customized
+    pub fn preset(preset: Preset) -> Self {
+        Self::default().with_preset(preset)
+    }
+
+    /// Sets the temperature, top_p and penalties to the values of a [`Preset`], leaving the other options untouched
+    pub fn with_preset(mut self, preset: Preset) -> Self {
+        let (temperature, top_p, presence_penalty, frequency_penalty) = preset.values();
+        self.temperature = Some(temperature);
+        self.top_p = Some(top_p);
+        self.presence_penalty = Some(presence_penalty);
+        self.frequency_penalty = Some(frequency_penalty);
+        self
+    }
+
+    /// Sets the model of the request, from either a [`Model`] or a raw model identifier
+    pub fn with_model<M: Into<Model>>(mut self, model: M) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Checks that `temperature` and `top_p` are not both set to non-default values.
+    ///
+    /// The API accepts it, but recommends altering only one of them, as they both control how random the output is and
+    /// combining them makes it hard to predict. See [`ClientOptions::with_strict_sampling`](crate::client::ClientOptions::with_strict_sampling).
+    pub fn validate_sampling(&self) -> crate::Result<()> {
+        match (self.temperature, self.top_p) {
+            (Some(temperature), Some(top_p)) if temperature != 1.0 && top_p != 1.0 => {
+                Err(crate::err::Error::InvalidRequest(format!(
+                    "temperature ({temperature}) and top_p ({top_p}) should not both be altered"
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks for combinations of options the API rejects, so that they fail with a descriptive
+    /// [`Error::InvalidRequest`](crate::err::Error::InvalidRequest) instead of a 400 response.
+    ///
+    /// The client runs it before sending a request, unless disabled with
+    /// [`ClientOptions::with_request_validation`](crate::client::ClientOptions::with_request_validation). It rejects:
+    ///
+    /// | Options | Rejected when |
+    /// |---|---|
+    /// | `n` | 0 or above 128 |
+    /// | `best_of` | lower than `n`, or combined with `stream` |
+    /// | `stream_options` | `stream` is not set |
+    /// | `top_logprobs` | above 20, or `logprobs` is not set |
+    /// | `parallel_tool_calls` | `tools` is not set |
+    /// | `audio` and `modalities` | only one of them asks for audio |
+    /// | `temperature`, `top_p`, penalties, `logprobs` and `logit_bias` | altered for a reasoning model, see [`Model::is_reasoning`] |
+    ///
+    /// The model is only checked when it is set.
+    pub fn validate(&self) -> crate::Result<()> {
+        let invalid = |message: String| Err(crate::err::Error::InvalidRequest(message));
+        let streaming = self.stream == Some(true);
+        let n = self.n.unwrap_or(1);
+        if !(1..=128).contains(&n) {
+            return invalid(format!("n ({n}) must be between 1 and 128"));
+        }
+        if let Some(best_of) = self.best_of {
+            if best_of < n {
+                return invalid(format!("best_of ({best_of}) must be at least n ({n})"));
+            }
+            if streaming {
+                return invalid("best_of can't be combined with stream".to_owned());
+            }
+        }
+        if self.stream_options.is_some() && !streaming {
+            return invalid("stream_options requires stream".to_owned());
+        }
+        if let Some(top_logprobs) = self.top_logprobs {
+            if top_logprobs > 20 {
+                return invalid(format!("top_logprobs ({top_logprobs}) must be at most 20"));
+            }
+            if self.logprobs != Some(true) {
+                return invalid("top_logprobs requires logprobs".to_owned());
+            }
+        }
+        if self.parallel_tool_calls.is_some() && self.tools.is_none() {
+            return invalid("parallel_tool_calls requires tools".to_owned());
+        }
+        let audio_output = self
+            .modalities
+            .as_ref()
+            .is_some_and(|modalities| modalities.contains(&Modality::Audio));
+        if audio_output != self.audio.is_some() {
+            return invalid("audio and the audio modality must be set together".to_owned());
+        }
+        if let Some(model) = self.model.as_ref().filter(|model| model.is_reasoning()) {
+            let altered = [
+                ("temperature", self.temperature.is_some_and(|t| t != 1.0)),
+                ("top_p", self.top_p.is_some_and(|p| p != 1.0)),
+                (
+                    "presence_penalty",
+                    self.presence_penalty.is_some_and(|p| p != 0.0),
+                ),
+                (
+                    "frequency_penalty",
+                    self.frequency_penalty.is_some_and(|p| p != 0.0),
+                ),
+                ("logprobs", self.logprobs == Some(true)),
+                ("logit_bias", self.logit_bias.is_some()),
+            ];
+            if let Some((option, _)) = altered.iter().find(|(_, altered)| *altered) {
+                return invalid(format!(
+                    "{option} is not supported by the reasoning model {model}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets `max_tokens` to the largest completion that fits in the context window of the model after the messages.
+    ///
+    /// The prompt is counted with the local tokenizer, leaving a margin of 32 tokens for its inaccuracy, and the result is capped
+    /// to [`Model::max_output_tokens`]. Fails with [`Error::InvalidRequest`](crate::err::Error::InvalidRequest) if the context window
+    /// of the model is unknown or the messages leave no room for a completion.
+    #[cfg(feature = "tokenizer")]
+    pub fn fill_max_tokens(mut self, messages: &[Message], model: &Model) -> crate::Result<Self> {
+        const MARGIN: usize = 32;
+        let context_window = model.context_window().ok_or_else(|| {
+            crate::err::Error::InvalidRequest(format!(
+                "the context window of {} is unknown",
+                model.id()
+            ))
+        })?;
+        let prompt_tokens = crate::tokenizer::count_message_tokens(model, messages);
+        let available = context_window.saturating_sub(prompt_tokens + MARGIN);
+        if available == 0 {
+            return Err(crate::err::Error::InvalidRequest(format!(
+                "the prompt of {prompt_tokens} tokens doesn't fit in the context window of {context_window} tokens of {}",
+                model.id()
+            )));
+        }
+        self.max_tokens = Some(
+            model
+                .max_output_tokens()
+                .map_or(available, |max| available.min(max)),
+        );
+        Ok(self)
+    }
+
+    /// Sets the logit bias of the request, checking that every bias is in the -100.0..=100.0 range accepted by the API
+    pub fn with_logit_bias(mut self, logit_bias: HashMap<u32, f32>) -> crate::Result<Self> {
+        if let Some((token, bias)) = logit_bias
+            .iter()
+            .find(|(_, bias)| !(-100.0..=100.0).contains(*bias))
+        {
+            return Err(crate::err::Error::InvalidRequest(format!(
+                "logit bias of token {token} must be between -100 and 100, got {bias}"
+            )));
+        }
+        self.logit_bias = Some(logit_bias);
+        Ok(self)
+    }
+
+    /// Sets the metadata of the request, checking that it has at most 16 pairs, with keys of at most 64 characters and values of at most 512 characters
+    pub fn with_metadata(mut self, metadata: HashMap<String, String>) -> crate::Result<Self> {
+        if metadata.len() > 16 {
+            return Err(crate::err::Error::InvalidRequest(format!(
+                "metadata must have at most 16 pairs, got {}",
+                metadata.len()
+            )));
+        }
+        if let Some(key) = metadata.keys().find(|key| key.chars().count() > 64) {
+            return Err(crate::err::Error::InvalidRequest(format!(
+                "metadata key {key:?} must be at most 64 characters long"
+            )));
+        }
+        if let Some(key) = metadata
+            .iter()
+            .find(|(_, value)| value.chars().count() > 512)
+            .map(|(key, _)| key)
+        {
+            return Err(crate::err::Error::InvalidRequest(format!(
+                "metadata value of {key:?} must be at most 512 characters long"
+            )));
+        }
+        self.metadata = Some(metadata);
+        Ok(self)
+    }
+}
+
+/// The format the embeddings are returned in
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EncodingFormat {
+    /// Arrays of floats
+    Float,
+    /// Base64 strings of little-endian `f32`s, about 4 times more compact
+    Base64,
+}
+
+/// Options of an embeddings request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct EmbeddingOptions {
+    ///model
+    ///string
+    ///Required
+    ///
+    ///ID of the embedding model to use, e.g. `text-embedding-3-small`. The client falls back to `text-embedding-3-small` when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<Model>,
+    ///encoding_format
+    ///string
+    ///Optional
+    ///Defaults to float
+    ///
+    ///The format to return the embeddings in. Either way, they are decoded into [`Embedding::embedding`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding_format: Option<EncodingFormat>,
+    ///dimensions
+    ///integer
+    ///Optional
+    ///
+    ///The number of dimensions of the embeddings. Only supported by `text-embedding-3` and later models.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<usize>,
+    ///user
+    ///string
+    ///Optional
+    ///
+    ///A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        validate_messages, Annotation, ApiErrorEnvelope, ApproximateLocation, AudioConfig,
+        AudioFormat, ChatCompletionChunk, CompletionOptions, Content, ContentFilterSeverity,
+        ContentPart, ConversationChoice, ConversationResponse, Embedding, FinishReason,
+        FunctionCall, InputAudio, InputAudioFormat, IntoMessages, Logprobs, Message, Modality,
+        Model, ObjectKind, Prediction, Preset, Role, SearchContextSize, ServiceTier, StopReason,
+        Tool, ToolCall, ToolCallFunction, Usage, UserLocation, Voice, WebSearchOptions,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_logit_bias_serialization() -> crate::Result<()> {
+        let options =
+            CompletionOptions::default().with_logit_bias(HashMap::from([(50256, -100.0)]))?;
+        let body = serde_json::to_value(options)?;
+        assert_eq!(body["logit_bias"], serde_json::json!({ "50256": -100.0 }));
+
+        let body = serde_json::to_value(CompletionOptions::default())?;
+        assert!(body.get("logit_bias").is_none());
+        Ok(())
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_tool_from_schema() -> crate::Result<()> {
+        #[derive(serde::Deserialize, schemars::JsonSchema)]
+        struct WeatherArgs {
+            /// The city to get the weather of
+            city: String,
+            days: Option<u8>,
+        }
+
+        let tool = Tool::from_schema::<WeatherArgs, _>("get_weather", "Gets the weather forecast");
+        assert_eq!(tool.function.name, "get_weather");
+        let parameters = tool.function.parameters.unwrap();
+        assert_eq!(parameters["type"], "object");
+        assert_eq!(parameters["required"], serde_json::json!(["city"]));
+        assert_eq!(
+            parameters["properties"]["city"]["description"],
+            "The city to get the weather of"
+        );
+        assert!(parameters.get("$schema").is_none());
+
+        let args: WeatherArgs = serde_json::from_str(r#"{"city":"Paris","days":3}"#)?;
+        assert_eq!((args.city.as_str(), args.days), ("Paris", Some(3)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = [
+            CompletionOptions::default(),
+            CompletionOptions {
+                n: Some(3),
+                best_of: Some(5),
+                ..Default::default()
+            },
+            CompletionOptions {
+                stream: Some(true),
+                stream_options: Some(Default::default()),
+                ..Default::default()
+            },
+            CompletionOptions {
+                model: Some(Model::O1),
+                temperature: Some(1.0),
+                ..Default::default()
+            },
+        ];
+        for options in valid {
+            assert!(options.validate().is_ok(), "{options:?}");
+        }
+
+        let invalid = [
+            CompletionOptions {
+                n: Some(0),
+                ..Default::default()
+            },
+            CompletionOptions {
+                n: Some(3),
+                best_of: Some(2),
+                ..Default::default()
+            },
+            CompletionOptions {
+                best_of: Some(2),
+                stream: Some(true),
+                ..Default::default()
+            },
+            CompletionOptions {
+                stream_options: Some(Default::default()),
+                ..Default::default()
+            },
+            CompletionOptions {
+                top_logprobs: Some(5),
+                ..Default::default()
+            },
+            CompletionOptions {
+                logprobs: Some(true),
+                top_logprobs: Some(21),
+                ..Default::default()
+            },
+            CompletionOptions {
+                parallel_tool_calls: Some(false),
+                ..Default::default()
+            },
+            CompletionOptions {
+                modalities: Some(vec![Modality::Text, Modality::Audio]),
+                ..Default::default()
+            },
+            CompletionOptions {
+                model: Some(Model::O3Mini),
+                presence_penalty: Some(0.5),
+                ..Default::default()
+            },
+        ];
+        for options in invalid {
+            assert!(
+                matches!(
+                    options.validate(),
+                    Err(crate::err::Error::InvalidRequest(_))
+                ),
+                "{options:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_preset() -> crate::Result<()> {
+        let options = CompletionOptions::preset(Preset::Creative).with_model(Model::Gpt4o);
+        let body = serde_json::to_value(options)?;
+        let number = |name: &str| body[name].as_f64().map(|value| value as f32);
+        assert_eq!(body["model"], "gpt-4o");
+        assert_eq!(number("temperature"), Some(1.1));
+        assert_eq!(number("top_p"), Some(1.0));
+        assert_eq!(number("presence_penalty"), Some(0.6));
+        assert_eq!(number("frequency_penalty"), Some(0.3));
+
+        let options = CompletionOptions {
+            max_tokens: Some(100),
+            ..Default::default()
+        }
+        .with_preset(Preset::Precise);
+        assert_eq!(options.max_tokens, Some(100));
+        assert_eq!(options.temperature, Some(0.2));
+        for preset in [Preset::Precise, Preset::Balanced, Preset::Creative] {
+            CompletionOptions::preset(preset).validate_sampling()?;
+        }
+
+        let options = CompletionOptions {
+            top_p: Some(0.1),
+            ..CompletionOptions::preset(Preset::Precise)
+        };
+        assert!(options.validate_sampling().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge() {
+        let defaults = CompletionOptions {
+            model: Some(Model::Gpt4o),
+            temperature: Some(0.2),
+            max_tokens: Some(100),
+            tools: Some(vec![Tool::function("search", "", serde_json::json!({}))]),
+            ..Default::default()
+        };
+        let overrides = CompletionOptions {
+            temperature: Some(0.9),
+            tools: Some(vec![]),
+            user: Some(42),
+            ..Default::default()
+        };
+        let merged = defaults.clone().merge(overrides);
+        assert_eq!(merged.model, Some(Model::Gpt4o));
+        assert_eq!(merged.temperature, Some(0.9));
+        assert_eq!(merged.max_tokens, Some(100));
+        assert_eq!(merged.tools, Some(vec![]));
+        assert_eq!(merged.user, Some(42));
+        assert_eq!(merged.top_p, None);
+        assert_eq!(defaults.clone().merge(Default::default()), defaults);
+    }
+
+    #[test]
+    fn test_unknown_fields() -> crate::Result<()> {
+        let response: ConversationResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-123",
+            "created": 1677652288,
+            "system_fingerprint": "fp_44709d6fcb",
+            "service_tier": "turbo",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "Hello",
+                    "audio": null,
+                    "annotations": [{ "type": "file_citation", "file_citation": {} }],
+                },
+                "logprobs": null,
+                "finish_reason": "stop",
+            }],
+        }))?;
+        assert_eq!(response.service_tier, Some(ServiceTier::Unknown));
+        assert_eq!(
+            response.choices[0].message.annotations,
+            [Annotation::Unknown]
+        );
+        assert_eq!(response.usage, Usage::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_audio() -> crate::Result<()> {
+        let options = CompletionOptions {
+            modalities: Some(vec![Modality::Text, Modality::Audio]),
+            audio: Some(AudioConfig {
+                voice: Voice::Alloy,
+                format: AudioFormat::Wav,
+            }),
+            ..Default::default()
+        };
+        let body = serde_json::to_value(options)?;
+        assert_eq!(body["modalities"], serde_json::json!(["text", "audio"]));
+        assert_eq!(
+            body["audio"],
+            serde_json::json!({ "voice": "alloy", "format": "wav" })
+        );
+
+        let message = Message {
+            role: "user".to_owned(),
+            content: Some(Content::Parts(vec![
+                ContentPart::Text {
+                    text: "What is said?".to_owned(),
+                },
+                ContentPart::InputAudio {
+                    input_audio: InputAudio {
+                        data: "UklGRg==".to_owned(),
+                        format: InputAudioFormat::Wav,
+                    },
+                },
+            ])),
+            ..Default::default()
+        };
+        validate_messages(std::slice::from_ref(&message))?;
+        assert_eq!(
+            serde_json::to_value(&message)?,
+            serde_json::json!({
+                "role": "user",
+                "content": [
+                    { "type": "text", "text": "What is said?" },
+                    { "type": "input_audio", "input_audio": { "data": "UklGRg==", "format": "wav" } },
+                ],
+            })
+        );
+        let parsed: Message = serde_json::from_value(serde_json::to_value(&message)?)?;
+        assert_eq!(parsed, message);
+
+        let reply: Message = serde_json::from_value(serde_json::json!({
+            "role": "assistant",
+            "content": null,
+            "audio": {
+                "id": "audio_abc123",
+                "expires_at": 1729018505,
+                "data": "UklGRg==",
+                "transcript": "Hello!",
+            },
+        }))?;
+        let audio = reply.audio.as_ref().unwrap();
+        assert_eq!(audio.transcript, "Hello!");
+        assert_eq!(audio.expires_at, 1729018505);
+        validate_messages(&[message, reply.clone()])?;
+        assert_eq!(
+            serde_json::to_value(&reply)?,
+            serde_json::json!({ "role": "assistant", "audio": { "id": "audio_abc123" } })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_image_file() -> crate::Result<()> {
+        let path = std::env::temp_dir().join(format!("chatgpt-test-{}.PNG", std::process::id()));
+        std::fs::write(&path, [0x89, b'P', b'N', b'G'])?;
+        let message = Message::user_with_image("What is it?", &path).await;
+        std::fs::remove_file(&path)?;
+        assert_eq!(
+            serde_json::to_value(message?)?,
+            serde_json::json!({
+                "role": "user",
+                "content": [
+                    { "type": "text", "text": "What is it?" },
+                    { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBORw==" } },
+                ],
+            })
+        );
+
+        assert!(matches!(
+            Message::user_with_image("What is it?", "image.bmp").await,
+            Err(crate::err::Error::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            Message::user_with_image("What is it?", path).await,
+            Err(crate::err::Error::IoError(_))
+        ));
+        assert_eq!(
+            ContentPart::image_bytes("image/png", [0x89, b'P', b'N', b'G']),
+            ContentPart::image_url("data:image/png;base64,iVBORw==")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_refusal() -> crate::Result<()> {
+        let message: Message = serde_json::from_value(serde_json::json!({
+            "role": "assistant",
+            "content": null,
+            "refusal": "I'm sorry, I cannot assist with that request.",
+        }))?;
+        assert_eq!(message.content, None);
+        assert_eq!(
+            message.refusal.as_deref(),
+            Some("I'm sorry, I cannot assist with that request.")
+        );
+        validate_messages(&[Message::from("Help me"), message])?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_result_messages() -> crate::Result<()> {
+        let call = Message {
+            role: "assistant".to_owned(),
+            tool_calls: Some(vec![ToolCall {
+                id: "call_abc".to_owned(),
+                kind: "function".to_owned(),
+                function: ToolCallFunction {
+                    name: "get_weather".to_owned(),
+                    arguments: "{}".to_owned(),
+                },
+            }]),
+            ..Default::default()
+        };
+        let result = Message::tool_result("call_abc", "sunny");
+        assert_eq!(
+            serde_json::to_value(&result)?,
+            serde_json::json!({ "role": "tool", "content": "sunny", "tool_call_id": "call_abc" })
+        );
+        validate_messages(&[Message::from("Weather?"), call, result])?;
+
+        assert_eq!(
+            serde_json::to_value(Message::function_result("get_weather", "sunny"))?,
+            serde_json::json!({ "role": "function", "content": "sunny", "name": "get_weather" })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stop_reason() -> crate::Result<()> {
+        let choices: Vec<ConversationChoice> = serde_json::from_value(serde_json::json!([
+            { "index": 0, "message": { "role": "assistant", "content": "1, 2" }, "finish_reason": "stop", "stop_reason": ", 3" },
+            { "index": 1, "message": { "role": "assistant", "content": "1, 2, 3" }, "finish_reason": "stop", "stop_reason": null },
+            { "index": 2, "message": { "role": "assistant", "content": "1" }, "finish_reason": "stop", "stop_reason": 128009 },
+        ]))?;
+        assert_eq!(choices[0].stop_sequence(), Some(", 3"));
+        assert_eq!(choices[1].stop_sequence(), None);
+        assert_eq!(choices[2].stop_reason, Some(StopReason::Token(128009)));
+        assert_eq!(choices[2].stop_sequence(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_logprobs() -> crate::Result<()> {
+        let choice: ConversationChoice = serde_json::from_value(serde_json::json!({
+            "index": 0,
+            "message": { "role": "assistant", "content": "Hi!" },
+            "logprobs": {
+                "content": [
+                    { "token": "Hi", "logprob": -0.5, "bytes": [72, 105], "top_logprobs": [] },
+                    { "token": "!", "logprob": -1.5, "bytes": [33], "top_logprobs": [
+                        { "token": "!", "logprob": -1.5, "bytes": [33] },
+                        { "token": ".", "logprob": -2.0, "bytes": [46] },
+                    ] },
+                ],
+                "refusal": null,
+            },
+        }))?;
+        let logprobs = choice.logprobs.unwrap();
+        assert_eq!(logprobs.content.as_ref().unwrap()[1].top_logprobs.len(), 2);
+        let stats = logprobs.stats().unwrap();
+        assert_eq!(stats.tokens, 2);
+        assert_eq!(stats.sum, -2.0);
+        assert_eq!(stats.mean, -1.0);
+        assert!((stats.perplexity - std::f64::consts::E).abs() < 1e-12);
+        assert_eq!(Logprobs::default().stats(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_function_call() -> crate::Result<()> {
+        let response: ConversationResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1677652288,
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "function_call": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" },
+                },
+                "finish_reason": "function_call",
+            }],
+        }))?;
+        let call = response.choices[0].message.function_call.as_ref().unwrap();
+        assert_eq!(call.name, "get_weather");
+        assert_eq!(
+            call.parse_arguments()?,
+            serde_json::json!({ "city": "Paris" })
+        );
+        assert!(FunctionCall {
+            arguments: "{".to_owned(),
+            ..call.clone()
+        }
+        .parse_arguments()
+        .is_err());
+
+        let options = CompletionOptions {
+            function_call: Some(FunctionCall {
+                name: "get_weather".to_owned(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(options)?["function_call"],
+            serde_json::json!({ "name": "get_weather" })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_filter_results() -> crate::Result<()> {
+        let choices: Vec<ConversationChoice> = serde_json::from_value(serde_json::json!([
+            {
+                "index": 0,
+                "message": { "role": "assistant", "content": "Hello!" },
+                "finish_reason": "stop",
+                "content_filter_results": {
+                    "hate": { "filtered": false, "severity": "safe" },
+                    "violence": { "filtered": false, "severity": "low" },
+                    "protected_material_text": { "filtered": false, "detected": false },
+                },
+            },
+            {
+                "index": 1,
+                "message": { "role": "assistant" },
+                "finish_reason": "content_filter",
+                "content_filter_results": {
+                    "violence": { "filtered": true, "severity": "high" },
+                    "jailbreak": { "filtered": false, "detected": true },
+                },
+            },
+            { "index": 2, "message": { "role": "assistant", "content": "Hi!" }, "finish_reason": "stop" },
+        ]))?;
+        let results = choices[0].content_filter_results.as_ref().unwrap();
+        assert_eq!(
+            results.violence.as_ref().unwrap().severity,
+            Some(ContentFilterSeverity::Low)
+        );
+        assert_eq!(results.sexual, None);
+        assert!(!choices[0].is_filtered());
+        let results = choices[1].content_filter_results.as_ref().unwrap();
+        assert!(results.is_filtered());
+        assert_eq!(results.jailbreak.as_ref().unwrap().detected, Some(true));
+        assert_eq!(choices[1].finish_reason, Some(FinishReason::ContentFilter));
+        assert!(choices[1].is_filtered());
+        assert!(!choices[2].is_filtered());
+        Ok(())
+    }
+
+    #[cfg(feature = "arbitrary-precision")]
+    #[test]
+    fn test_arbitrary_precision() -> crate::Result<()> {
+        // Both numbers are larger than u64::MAX, so they would be rounded to an f64 without the feature
+        let response: ConversationResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1677652288,
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "function_call": { "name": "lookup", "arguments": r#"{"id":12345678901234567890123}"# },
+                },
+                "finish_reason": "function_call",
+            }],
+        }))?;
+        let call = response.choices[0].message.function_call.as_ref().unwrap();
+        assert_eq!(
+            call.parse_arguments()?["id"].to_string(),
+            "12345678901234567890123"
+        );
+
+        let error: ApiErrorEnvelope = serde_json::from_str(
+            r#"{"error":{"message":"Bad request","type":"invalid_request_error","code":98765432109876543210}}"#,
+        )?;
+        assert_eq!(error.error.code.as_deref(), Some("98765432109876543210"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_kind() -> crate::Result<()> {
+        let response: ConversationResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1677652288,
+            "choices": [],
+        }))?;
+        assert_eq!(response.object, ObjectKind::ChatCompletion);
+        let chunk: ChatCompletionChunk = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-123",
+            "created": 1677652288,
+            "model": "gpt-4o",
+            "choices": [],
+        }))?;
+        assert_eq!(chunk.object, ObjectKind::ChatCompletionChunk);
+
+        let object: ObjectKind = serde_json::from_str("\"response\"")?;
+        assert_eq!(object, ObjectKind::Other("response".to_owned()));
+        assert_eq!(serde_json::to_string(&object)?, "\"response\"");
+        assert_eq!(serde_json::to_string(&ObjectKind::List)?, "\"list\"");
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata() -> crate::Result<()> {
+        let options = CompletionOptions {
+            store: Some(true),
+            ..Default::default()
+        }
+        .with_metadata(HashMap::from([("feature".to_owned(), "chat".to_owned())]))?;
+        let body = serde_json::to_value(options)?;
+        assert_eq!(body["store"], true);
+        assert_eq!(body["metadata"], serde_json::json!({ "feature": "chat" }));
+        let body = serde_json::to_value(CompletionOptions::default())?;
+        assert!(body.get("store").is_none() && body.get("metadata").is_none());
+
+        let too_many = (0..17).map(|i| (i.to_string(), String::new())).collect();
+        assert!(CompletionOptions::default()
+            .with_metadata(too_many)
+            .is_err());
+        let long_key = HashMap::from([("k".repeat(65), String::new())]);
+        assert!(CompletionOptions::default()
+            .with_metadata(long_key)
+            .is_err());
+        let long_value = HashMap::from([(String::new(), "v".repeat(513))]);
+        assert!(CompletionOptions::default()
+            .with_metadata(long_value)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_prediction() -> crate::Result<()> {
+        let options = CompletionOptions {
+            prediction: Some(Prediction::content("fn main() {}")),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(options)?["prediction"],
+            serde_json::json!({ "type": "content", "content": "fn main() {}" })
+        );
+        let prediction = Prediction::content(vec![ContentPart::Text {
+            text: "fn main() {}".to_owned(),
+        }]);
+        assert_eq!(
+            serde_json::to_value(&prediction)?,
+            serde_json::json!({
+                "type": "content",
+                "content": [{ "type": "text", "text": "fn main() {}" }],
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<Prediction>(serde_json::to_value(&prediction)?)?,
+            prediction
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_base64_embedding() -> crate::Result<()> {
+        use base64::Engine;
+
+        let floats = [0.5f32, -1.25, 3.0e-7, f32::MAX];
+        let bytes: Vec<u8> = floats.iter().flat_map(|f| f.to_le_bytes()).collect();
+        let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+        let embedding: Embedding = serde_json::from_value(serde_json::json!({
+            "object": "embedding",
+            "index": 0,
+            "embedding": encoded,
+        }))?;
+        assert_eq!(embedding.embedding, floats);
+
+        let embedding: Embedding = serde_json::from_value(serde_json::json!({
+            "index": 1,
+            "embedding": [0.5, -1.25],
+        }))?;
+        assert_eq!(embedding.embedding, [0.5, -1.25]);
+
+        // 3 bytes, which is not a whole f32
+        let truncated = serde_json::json!({ "index": 0, "embedding": "AAAA" });
+        assert!(serde_json::from_value::<Embedding>(truncated).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_logit_bias_range() {
+        assert!(CompletionOptions::default()
+            .with_logit_bias(HashMap::from([(1, 100.5)]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_model_serialization() -> crate::Result<()> {
+        let options = CompletionOptions::default().with_model(Model::Gpt4oMini);
+        assert_eq!(serde_json::to_value(options)?["model"], "gpt-4o-mini");
+
+        let options = CompletionOptions::default().with_model("my-fine-tuned-model");
+        assert_eq!(
+            serde_json::to_value(options)?["model"],
+            "my-fine-tuned-model"
+        );
+
+        let model: Model = serde_json::from_str("\"o3-mini\"")?;
+        assert_eq!(model, Model::O3Mini);
+        assert_eq!(model.to_string(), "o3-mini");
+        assert_eq!(
+            Model::from("gpt-5-preview"),
+            Model::Custom("gpt-5-preview".to_owned())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_tool_calls_serialization() -> crate::Result<()> {
+        let options = CompletionOptions {
+            tools: Some(vec![Tool::function(
+                "get_weather",
+                "Gets the current weather of a city",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": { "city": { "type": "string" } },
+                }),
+            )]),
+            parallel_tool_calls: Some(false),
+            ..Default::default()
+        };
+        let body = serde_json::to_value(options)?;
+        assert_eq!(body["parallel_tool_calls"], false);
+        assert_eq!(body["tools"][0]["type"], "function");
+        assert_eq!(body["tools"][0]["function"]["name"], "get_weather");
+
+        let body = serde_json::to_value(CompletionOptions::default())?;
+        assert!(body.get("parallel_tool_calls").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_messages() {
+        let call = |id: &str| ToolCall {
+            id: id.to_owned(),
+            kind: "function".to_owned(),
+            function: ToolCallFunction {
+                name: "get_weather".to_owned(),
+                arguments: "{}".to_owned(),
+            },
+        };
+        let assistant = Message {
+            role: "assistant".to_owned(),
+            tool_calls: Some(vec![call("call_a"), call("call_b")]),
+            ..Default::default()
+        };
+        let result = |id: &str| Message {
+            role: "tool".to_owned(),
+            content: Some("sunny".into()),
+            tool_call_id: Some(id.to_owned()),
+            ..Default::default()
+        };
+        let user = Message::from("What's the weather?");
+
+        let valid = [
+            user.clone(),
+            assistant.clone(),
+            result("call_b"),
+            result("call_a"),
+            Message::from((Role::Assistant, "Sunny")),
+        ];
+        assert!(validate_messages(&valid).is_ok());
+
+        assert!(validate_messages(&[]).is_err());
+        assert!(validate_messages(&[Message::from("")]).is_err());
+        assert!(validate_messages(&[user.clone(), result("call_a")]).is_err());
+        let unanswered = [user.clone(), assistant.clone(), result("call_a")];
+        assert!(validate_messages(&unanswered).is_err());
+        let interrupted = [user.clone(), assistant, user.clone(), result("call_a")];
+        assert!(validate_messages(&interrupted).is_err());
+        let unknown = Message {
+            role: "robot".to_owned(),
+            ..user
+        };
+        assert!(validate_messages(&[unknown]).is_err());
+    }
+
+    #[test]
+    fn test_message_conversions() {
+        let message = Message::from("hello");
+        assert_eq!(message.role, "user");
+        assert_eq!(message.content, Some("hello".into()));
+
+        let messages = vec![
+            (Role::User, "What is 2 + 2?"),
+            (Role::Assistant, "4"),
+            (Role::User, "And times 3?"),
+        ]
+        .into_messages();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1].role, "assistant");
+        assert_eq!(messages[1].content, Some("4".into()));
+
+        assert_eq!("hello".into_messages(), vec![message.clone()]);
+        assert_eq!([message.clone()].into_messages(), vec![message]);
+    }
+
+    #[test]
+    fn test_roles() -> crate::Result<()> {
+        for role in [
+            Role::System,
+            Role::Developer,
+            Role::User,
+            Role::Assistant,
+            Role::Tool,
+            Role::Function,
+        ] {
+            let serialized = serde_json::to_value(role)?;
+            assert_eq!(serialized, role.as_str());
+            assert_eq!(serde_json::from_value::<Role>(serialized)?, role);
+            assert_eq!(role.as_str().parse::<Role>()?, role);
+            assert_eq!(Message::from((role, "content")).role(), Some(role));
+        }
+        assert!("robot".parse::<Role>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_usage_details() -> crate::Result<()> {
+        let usage: Usage = serde_json::from_value(serde_json::json!({
+            "prompt_tokens": 2006,
+            "completion_tokens": 300,
+            "total_tokens": 2306,
+            "prompt_tokens_details": { "cached_tokens": 1920 },
+            "completion_tokens_details": { "reasoning_tokens": 128 },
+        }))?;
+        assert_eq!(
+            usage.prompt_tokens_details.unwrap().cached_tokens,
+            Some(1920)
+        );
+        assert_eq!(
+            usage.completion_tokens_details.unwrap().reasoning_tokens,
+            Some(128)
+        );
+
+        let usage: Usage = serde_json::from_value(serde_json::json!({
+            "prompt_tokens": 9,
+            "completion_tokens": 12,
+            "total_tokens": 21,
+        }))?;
+        assert_eq!(usage.prompt_tokens_details, None);
+        assert_eq!(usage.completion_tokens_details, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_usage_sum() -> crate::Result<()> {
+        let usages: Vec<Usage> = serde_json::from_value(serde_json::json!([
+            {
+                "prompt_tokens": 2006,
+                "completion_tokens": 300,
+                "total_tokens": 2306,
+                "prompt_tokens_details": { "cached_tokens": 1920 },
+                "completion_tokens_details": { "reasoning_tokens": 128 },
+            },
+            {
+                "prompt_tokens": 10,
+                "completion_tokens": 20,
+                "total_tokens": 30,
+                "completion_tokens_details": { "reasoning_tokens": 12, "audio_tokens": 4 },
+            },
+            { "prompt_tokens": 1, "completion_tokens": 2, "total_tokens": 3 },
+        ]))?;
+        let total = Usage::sum(&usages);
+        assert_eq!(
+            (
+                total.prompt_tokens,
+                total.completion_tokens,
+                total.total_tokens
+            ),
+            (2017, 322, 2339)
+        );
+        let prompt_details = total.prompt_tokens_details.clone().unwrap();
+        assert_eq!(prompt_details.cached_tokens, Some(1920));
+        assert_eq!(prompt_details.audio_tokens, None);
+        let completion_details = total.completion_tokens_details.clone().unwrap();
+        assert_eq!(completion_details.reasoning_tokens, Some(140));
+        assert_eq!(completion_details.audio_tokens, Some(4));
+        assert!(!total.estimated);
+
+        assert_eq!(usages.iter().sum::<Usage>(), total);
+        assert_eq!(usages.clone().into_iter().sum::<Usage>(), total);
+        let mut running = usages[0].clone() + usages[1].clone();
+        running += Usage {
+            estimated: true,
+            ..usages[2].clone()
+        };
+        assert_eq!(running.total_tokens, 2339);
+        assert!(running.estimated);
+        assert_eq!(Usage::sum([]), Usage::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_service_tier() -> crate::Result<()> {
+        let options = CompletionOptions {
+            service_tier: Some(ServiceTier::Flex),
+            ..Default::default()
+        };
+        assert_eq!(serde_json::to_value(options)?["service_tier"], "flex");
+        let body = serde_json::to_value(CompletionOptions::default())?;
+        assert!(body.get("service_tier").is_none());
+
+        let response: ConversationResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1677652288,
+            "choices": [],
+            "usage": { "prompt_tokens": 9, "completion_tokens": 12, "total_tokens": 21 },
+            "service_tier": "default",
+        }))?;
+        assert_eq!(response.service_tier, Some(ServiceTier::Default));
+        Ok(())
+    }
+
+    #[test]
+    fn test_web_search() -> crate::Result<()> {
+        let options = CompletionOptions {
+            web_search_options: Some(WebSearchOptions {
+                search_context_size: Some(SearchContextSize::Low),
+                user_location: Some(UserLocation::Approximate {
+                    approximate: ApproximateLocation {
+                        country: Some("FR".to_owned()),
+                        ..Default::default()
+                    },
+                }),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(options)?["web_search_options"],
+            serde_json::json!({
+                "search_context_size": "low",
+                "user_location": { "type": "approximate", "approximate": { "country": "FR" } },
+            })
+        );
+
+        let message: Message = serde_json::from_value(serde_json::json!({
+            "role": "assistant",
+            "content": "See example.com",
+            "annotations": [{
+                "type": "url_citation",
+                "url_citation": {
+                    "start_index": 4,
+                    "end_index": 15,
+                    "url": "https://example.com",
+                    "title": "Example",
+                },
+            }],
+        }))?;
+        let Annotation::UrlCitation { url_citation } = &message.annotations[0] else {
+            panic!("expected a URL citation, got {:?}", message.annotations[0]);
+        };
+        assert_eq!(url_citation.url, "https://example.com");
+        assert!(serde_json::to_value(&message)?.get("annotations").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_display() -> crate::Result<()> {
+        assert_eq!(Role::Assistant.to_string(), "assistant");
+        assert_eq!(Role::Developer.to_string(), "developer");
+        assert_eq!(Message::from("hello").to_string(), "user: hello");
+
+        let message: Message = serde_json::from_value(serde_json::json!({
+            "role": "assistant",
+            "content": null,
+            "tool_calls": [{
+                "id": "call_a",
+                "type": "function",
+                "function": { "name": "get_weather", "arguments": "{}" },
+            }],
+        }))?;
+        assert_eq!(message.to_string(), "assistant: [calls get_weather]");
+
+        let message = Message {
+            role: "user".to_owned(),
+            content: Some(Content::Parts(vec![
+                ContentPart::text("What is this?"),
+                ContentPart::image_url("https://example.com/cat.png"),
+                ContentPart::InputAudio {
+                    input_audio: InputAudio {
+                        data: "UklGRg==".to_owned(),
+                        format: InputAudioFormat::Wav,
+                    },
+                },
+            ])),
+            ..Default::default()
+        };
+        assert_eq!(message.to_string(), "user: What is this? [image] [audio]");
+        Ok(())
+    }
 }